@@ -0,0 +1,449 @@
+//! Convert the text members of tar and zip archives, copying the others
+//! unchanged.
+
+use {
+    crate::files,
+    anyhow::{Context, Error, Result},
+    encoding_rs::{Encoding, UTF_16BE, UTF_16LE},
+    ignore::gitignore::{Gitignore, GitignoreBuilder},
+    std::{
+        io::{Read, Seek, Write},
+        path::Path,
+    },
+    zip::{write::SimpleFileOptions, ZipArchive, ZipWriter},
+};
+
+/// The magic bytes at the start of a zip archive, or of an empty one.
+const ZIP_MAGIC: &[&[u8]] = &[b"PK\x03\x04", b"PK\x05\x06"];
+
+/// An archive format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// A tar archive, possibly compressed as a whole.
+    Tar,
+    /// A zip archive.
+    Zip,
+}
+
+impl Format {
+    /// The format of an archive starting with `start`, assuming tar for
+    /// anything which is not zip.
+    pub fn detect(start: &[u8]) -> Self {
+        if ZIP_MAGIC.iter().any(|magic| start.starts_with(magic)) {
+            Format::Zip
+        } else {
+            Format::Tar
+        }
+    }
+}
+
+/// Which members of an archive are converted.
+///
+/// Members are selected by name with patterns in the syntax of a `.gitignore`
+/// file. A member is converted when it matches one of the includes, or there
+/// are none, and none of the excludes. Members which look binary are never
+/// converted.
+#[derive(Debug, Clone)]
+pub struct Members {
+    includes: Option<Gitignore>,
+    excludes: Gitignore,
+    encoding: Option<&'static Encoding>,
+}
+
+fn patterns(patterns: &[String]) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new("");
+    for pattern in patterns {
+        builder
+            .add_line(None, pattern)
+            .with_context(|| format!("invalid pattern `{}`", pattern))?;
+    }
+
+    Ok(builder.build()?)
+}
+
+impl Members {
+    /// Select members by the `includes` and `excludes` patterns, members in
+    /// `encoding` being checked for binary data only when it is not UTF-16.
+    pub fn new(
+        includes: &[String],
+        excludes: &[String],
+        encoding: Option<&'static Encoding>,
+    ) -> Result<Self> {
+        Ok(Members {
+            includes: match includes {
+                [] => None,
+                _ => Some(patterns(includes)?),
+            },
+            excludes: patterns(excludes)?,
+            encoding,
+        })
+    }
+
+    fn selects(&self, name: &str) -> bool {
+        let name = Path::new(name);
+        let included = match &self.includes {
+            Some(includes) => files::matches(includes, name, false),
+            None => true,
+        };
+
+        included && !files::matches(&self.excludes, name, false)
+    }
+
+    fn converts(&self, name: &str, data: &[u8]) -> bool {
+        let utf_16 = self.encoding == Some(UTF_16LE) || self.encoding == Some(UTF_16BE);
+        self.selects(name) && (utf_16 || !files::looks_binary(data))
+    }
+}
+
+/// Copy the tar archive read from `input` to `output`, converting the
+/// contents of its selected members with `convert`, which returns `None` to
+/// have a member copied unchanged.
+///
+/// The entries keep their names, modes, owners, modification times and PAX
+/// extended attributes, only their sizes changing. Only the selected members
+/// are read into memory, the others being streamed through. Sparse files
+/// cannot be copied faithfully, and fail the conversion.
+pub fn convert_tar<R, W, F>(input: R, output: W, members: &Members, mut convert: F) -> Result<W>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(&str, &[u8]) -> Result<Option<Vec<u8>>>,
+{
+    let mut archive = tar::Archive::new(input);
+    let mut builder = tar::Builder::new(output);
+
+    for entry in archive.entries().context("could not read the archive")? {
+        let mut entry = entry.context("could not read the archive")?;
+        let path = entry.path()?.into_owned();
+        let name = path.to_string_lossy().into_owned();
+        let link = entry.link_name()?.map(|link| link.into_owned());
+        let mut header = entry.header().clone();
+        header.set_size(entry.size());
+
+        let mut sparse = header.entry_type().is_gnu_sparse();
+        let mut extensions = Vec::new();
+        if !header.entry_type().is_pax_global_extensions() {
+            for extension in entry.pax_extensions()?.into_iter().flatten() {
+                let extension = extension?;
+                let key = String::from_utf8_lossy(extension.key_bytes()).into_owned();
+                sparse |= key.starts_with("GNU.sparse.");
+                extensions.push((key, extension.value_bytes().to_vec()));
+            }
+        }
+        if sparse {
+            return Err(Error::msg(format!(
+                "member `{}` is a sparse file, which cannot be copied",
+                name
+            )));
+        }
+
+        if !header.entry_type().is_file() || !members.selects(&name) {
+            builder.append_pax_extensions(pax(&extensions))?;
+            match link {
+                Some(link) => builder.append_link(&mut header, &path, link)?,
+                None => builder.append_data(&mut header, &path, &mut entry)?,
+            }
+            continue;
+        }
+
+        let mut data = Vec::new();
+        entry
+            .read_to_end(&mut data)
+            .with_context(|| format!("could not read member `{}`", name))?;
+        if members.converts(&name, &data) {
+            let converted = convert(&name, &data)
+                .with_context(|| format!("could not convert member `{}`", name))?;
+            if let Some(converted) = converted {
+                data = converted;
+            }
+        }
+
+        header.set_size(data.len() as u64);
+        for (key, value) in &mut extensions {
+            if key == "size" {
+                *value = data.len().to_string().into_bytes();
+            }
+        }
+        builder.append_pax_extensions(pax(&extensions))?;
+        builder.append_data(&mut header, &path, data.as_slice())?;
+    }
+
+    Ok(builder.into_inner()?)
+}
+
+/// The PAX extended attributes in `extensions`, as taken by a tar builder.
+fn pax(extensions: &[(String, Vec<u8>)]) -> impl Iterator<Item = (&str, &[u8])> {
+    extensions
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_slice()))
+}
+
+/// Copy the zip archive read from `input` to `output`, converting the
+/// contents of its selected members with `convert`, which returns `None` to
+/// have a member copied unchanged.
+///
+/// Converted members keep their names, compression methods, permissions and
+/// modification times, while all other members are copied byte for byte.
+pub fn convert_zip<R, W, F>(input: R, output: W, members: &Members, mut convert: F) -> Result<W>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+    F: FnMut(&str, &[u8]) -> Result<Option<Vec<u8>>>,
+{
+    let mut archive = ZipArchive::new(input).context("could not read the archive")?;
+    let mut writer = ZipWriter::new(output);
+    writer.set_raw_comment(archive.comment().to_vec().into_boxed_slice());
+
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index)?;
+        let name = file.name().to_owned();
+
+        let mut data = Vec::new();
+        if !file.is_dir() && members.selects(&name) {
+            file.read_to_end(&mut data)
+                .with_context(|| format!("could not read member `{}`", name))?;
+        }
+        let converted = if !data.is_empty() && members.converts(&name, &data) {
+            convert(&name, &data).with_context(|| format!("could not convert member `{}`", name))?
+        } else {
+            None
+        };
+        let converted = match converted {
+            Some(converted) => converted,
+            None => {
+                // Read again from the start, to copy it as it is
+                drop(file);
+                writer.raw_copy_file(archive.by_index_raw(index)?)?;
+                continue;
+            }
+        };
+
+        let mut options = SimpleFileOptions::default()
+            .compression_method(file.compression())
+            .large_file(converted.len() as u64 >= u64::from(u32::MAX));
+        if let Some(modified) = file.last_modified() {
+            options = options.last_modified_time(modified);
+        }
+        if let Some(mode) = file.unix_mode() {
+            options = options.unix_permissions(mode);
+        }
+
+        writer.start_file(name, options)?;
+        writer.write_all(&converted)?;
+    }
+
+    Ok(writer.finish()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::io::Cursor};
+
+    fn uppercase(_name: &str, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(Some(data.to_ascii_uppercase()))
+    }
+
+    fn tar(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, data) in members {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o640);
+            header.set_mtime(1_000_000_000);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+
+        builder.into_inner().unwrap()
+    }
+
+    fn zip(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in members {
+            let options = SimpleFileOptions::default().unix_permissions(0o640);
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(data).unwrap();
+        }
+
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn detect_format() {
+        assert_eq!(Format::detect(&zip(&[])), Format::Zip);
+        assert_eq!(Format::detect(&zip(&[("a.txt", b"a")])), Format::Zip);
+        assert_eq!(Format::detect(&tar(&[("a.txt", b"a")])), Format::Tar);
+    }
+
+    #[test]
+    fn convert_tar_members() {
+        let long = format!("{}/c.txt", "dir".repeat(40));
+        let input = tar(&[
+            ("a.txt", b"some text"),
+            ("b.bin", b"\x00some bytes"),
+            ("skip.log", b"log"),
+            (&long, b"long name"),
+        ]);
+        let members = Members::new(&[], &[String::from("*.log")], None).unwrap();
+
+        let output = convert_tar(input.as_slice(), Vec::new(), &members, uppercase).unwrap();
+
+        let mut archive = tar::Archive::new(output.as_slice());
+        let entries: Vec<_> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data).unwrap();
+                let header = entry.header();
+                assert_eq!(header.mode().unwrap(), 0o640);
+                assert_eq!(header.mtime().unwrap(), 1_000_000_000);
+                (entry.path().unwrap().to_string_lossy().into_owned(), data)
+            })
+            .collect();
+
+        assert_eq!(
+            entries,
+            [
+                (String::from("a.txt"), b"SOME TEXT".to_vec()),
+                (String::from("b.bin"), b"\x00some bytes".to_vec()),
+                (String::from("skip.log"), b"log".to_vec()),
+                (long, b"LONG NAME".to_vec()),
+            ]
+        );
+    }
+
+    fn copy(_name: &str, _data: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    fn tar_with_pax(name: &str, data: &[u8], extensions: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        builder
+            .append_pax_extensions(extensions.iter().copied())
+            .unwrap();
+        let mut header = tar::Header::new_ustar();
+        header.set_size(data.len() as u64);
+        header.set_cksum();
+        builder.append_data(&mut header, name, data).unwrap();
+
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn convert_tar_keeps_pax_extensions() {
+        let input = tar_with_pax(
+            "a.txt",
+            b"some text",
+            &[("SCHILY.xattr.user.note", b"kept"), ("size", b"9")],
+        );
+        let members = Members::new(&[], &[], None).unwrap();
+
+        let output = convert_tar(input.as_slice(), Vec::new(), &members, uppercase).unwrap();
+
+        let mut archive = tar::Archive::new(output.as_slice());
+        let mut entry = archive.entries().unwrap().next().unwrap().unwrap();
+        let extensions: Vec<_> = entry
+            .pax_extensions()
+            .unwrap()
+            .unwrap()
+            .map(|extension| {
+                let extension = extension.unwrap();
+                (
+                    extension.key().unwrap().to_owned(),
+                    extension.value().unwrap().to_owned(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            extensions,
+            [
+                (String::from("SCHILY.xattr.user.note"), String::from("kept")),
+                (String::from("size"), String::from("9")),
+            ]
+        );
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"SOME TEXT");
+    }
+
+    #[test]
+    fn convert_tar_rejects_sparse_files() {
+        let input = tar_with_pax("a.txt", b"some text", &[("GNU.sparse.major", b"1")]);
+        let members = Members::new(&[], &[], None).unwrap();
+
+        let error = convert_tar(input.as_slice(), Vec::new(), &members, uppercase).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "member `a.txt` is a sparse file, which cannot be copied"
+        );
+    }
+
+    #[test]
+    fn convert_tar_copies_unconverted_members() {
+        let input = tar(&[("a.txt", b"some text")]);
+        let members = Members::new(&[], &[], None).unwrap();
+
+        let output = convert_tar(input.as_slice(), Vec::new(), &members, copy).unwrap();
+
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn convert_zip_copies_unconverted_members() {
+        let input = zip(&[("a.txt", b"some text")]);
+        let members = Members::new(&[], &[], None).unwrap();
+
+        let output = convert_zip(
+            Cursor::new(input.clone()),
+            Cursor::new(Vec::new()),
+            &members,
+            copy,
+        )
+        .unwrap()
+        .into_inner();
+
+        let mut archive = ZipArchive::new(Cursor::new(output)).unwrap();
+        let mut data = Vec::new();
+        archive
+            .by_name("a.txt")
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, b"some text");
+    }
+
+    #[test]
+    fn convert_zip_members() {
+        let input = zip(&[
+            ("a.txt", b"some text"),
+            ("b.bin", b"\x00some bytes"),
+            ("docs/c.md", b"markdown"),
+        ]);
+        let members = Members::new(&[String::from("*.txt")], &[], None).unwrap();
+
+        let output = convert_zip(
+            Cursor::new(input),
+            Cursor::new(Vec::new()),
+            &members,
+            uppercase,
+        )
+        .unwrap()
+        .into_inner();
+
+        let mut archive = ZipArchive::new(Cursor::new(output)).unwrap();
+        let mut read = |name: &str| {
+            let mut file = archive.by_name(name).unwrap();
+            assert_eq!(file.unix_mode().map(|mode| mode & 0o777), Some(0o640));
+            let mut data = Vec::new();
+            file.read_to_end(&mut data).unwrap();
+            data
+        };
+
+        assert_eq!(read("a.txt"), b"SOME TEXT");
+        assert_eq!(read("b.bin"), b"\x00some bytes");
+        assert_eq!(read("docs/c.md"), b"markdown");
+    }
+}
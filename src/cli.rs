@@ -1,48 +1,100 @@
 use {
-    crate::convert::{random::RandomConvertor, simple::SimpleConvertor, Convertor},
+    angry::{
+        archive::{self, Members},
+        compress::{Compression, Compressor, Decompressor},
+        convert::{
+            alternate::{Case, Restart},
+            casing::Locale,
+            leet::Level,
+            normalize::Form,
+            parse_percent,
+            random::Algorithm,
+            target::{Target, Targets},
+            Randomness, Settings,
+        },
+        driver::{self, InvalidUtf8, LineEndings, Lines, Separator, Utf8Errors},
+        encoding::{parse_encoding, Decoder, Encoder, Malformed},
+        files::{self, FileId, Search},
+        pipeline::STAGES,
+        Conversion, Convertor, Pipeline,
+    },
     anyhow::{Context, Error, Result},
+    encoding_rs::{Encoding, UTF_8},
+    rayon::prelude::*,
     std::{
-        fs::File,
-        io::{self, BufRead, BufReader, Cursor, LineWriter, Write},
-        path::PathBuf,
+        collections::HashMap,
+        fs::{self, File, OpenOptions},
+        io::{self, BufRead, BufReader, Cursor, IntoInnerError, LineWriter, Write},
+        path::{Path, PathBuf},
     },
     structopt::{clap::ArgGroup, StructOpt},
 };
 
-#[derive(Debug)]
-enum Conversion {
-    Uppercase,
-    Lowercase,
-    Reverse,
-    Random(u8, Option<u8>),
-}
-
-fn parse_percent(s: &str) -> Result<u8> {
-    let i: u8 = s.parse()?;
-
-    if i > 100 {
-        Err(Error::msg(format!(
-            "percentage must be between 0 and 100, was {}",
-            i
-        )))
-    } else {
-        Ok(i)
-    }
-}
-
 /// Take the passed text and make it aNgrY by applying random capitalisation.
 #[derive(StructOpt, Debug)]
 #[structopt(group = ArgGroup::with_name("action").required(true))]
 pub struct Cli {
-    /// The path to the input file to read, optional
-    #[structopt(parse(from_os_str), short, long)]
-    input: Option<PathBuf>,
+    /// The paths to the input files to read, optional. Each may also be a
+    /// glob such as "*.txt" or, with -R, a directory
+    #[structopt(parse(from_os_str), short, long = "input", number_of_values = 1)]
+    inputs: Vec<PathBuf>,
 
     /// The path to the output file to write, optional
     #[structopt(parse(from_os_str), short, long)]
     output: Option<PathBuf>,
 
-    /// Percentage chance of random flip
+    /// Convert the files in input directories and their subdirectories
+    #[structopt(short = "R", long)]
+    recursive: bool,
+
+    /// Skip the files found in directories or by globs which match a pattern,
+    /// in the syntax of a .gitignore file
+    #[structopt(long = "exclude", number_of_values = 1)]
+    excludes: Vec<String>,
+
+    /// Convert the text members of a tar or zip archive, copying the others
+    /// unchanged, and write the archive to the output
+    #[structopt(
+        parse(from_os_str),
+        long,
+        conflicts_with_all = &["inputs", "content", "in-place", "out-dir", "suffix"]
+    )]
+    archive: Option<PathBuf>,
+
+    /// Only convert the archive members matching a pattern, in the syntax of
+    /// a .gitignore file. Members are also skipped when they match --exclude
+    #[structopt(long = "member", number_of_values = 1, requires = "archive")]
+    members: Vec<String>,
+
+    /// Write the conversion of each input file to a directory, mirroring the
+    /// input paths
+    #[structopt(parse(from_os_str), long, conflicts_with_all = &["output", "suffix"])]
+    out_dir: Option<PathBuf>,
+
+    /// Write the conversion of each input file next to it, adding a suffix
+    /// such as ".angry.txt" to its name
+    #[structopt(long, conflicts_with = "output")]
+    suffix: Option<String>,
+
+    /// Compress the output, by default when the output file name ends in .gz
+    /// or .zst. Compressed input is always detected and decompressed
+    #[structopt(long, possible_values = Compression::NAMES)]
+    compress: Option<Compression>,
+
+    /// Refuse to overwrite an output file which already exists
+    #[structopt(long)]
+    no_clobber: bool,
+
+    /// Rewrite each input file with its conversion, keeping the original as
+    /// a backup when a suffix is given, such as --in-place=.bak
+    #[structopt(
+        long,
+        require_equals = true,
+        conflicts_with_all = &["output", "out-dir", "suffix", "content"]
+    )]
+    in_place: Option<Option<String>>,
+
+    /// Percentage chance of random flip or leet substitution
     #[structopt(short, long, default_value = "50", parse(try_from_str = parse_percent))]
     percent: u8,
 
@@ -50,6 +102,104 @@ pub struct Cli {
     #[structopt(short, long)]
     step: Option<Option<u8>>,
 
+    /// Seed for the random number generator, to make the output reproducible
+    #[structopt(long)]
+    seed: Option<u64>,
+
+    /// Seed the random choices for each line from its content, so the same
+    /// line is always converted the same way
+    #[structopt(long)]
+    stable: bool,
+
+    /// Version of the random algorithm: v1 flips each letter on its own, v2
+    /// adds grapheme clusters and context-sensitive casing, and v3 also
+    /// emphasises scripts without case. Defaults to v3, or to v1 with --seed
+    /// or --stable so that a seed keeps its output
+    #[structopt(long, possible_values = Algorithm::NAMES)]
+    algorithm: Option<Algorithm>,
+
+    /// Language whose case mappings to use, such as tr, az or lt, defaulting
+    /// to the one set by LC_ALL, LC_CTYPE or LANG
+    #[structopt(long)]
+    locale: Option<Locale>,
+
+    /// Convert every character to exactly one character, such as ß to ẞ,
+    /// leaving it unchanged when there is no such conversion
+    #[structopt(long)]
+    preserve_length: bool,
+
+    /// Normalize the text to the given Unicode form before and after
+    /// converting it
+    #[structopt(long, possible_values = Form::NAMES)]
+    normalize: Option<Form>,
+
+    /// Only convert the characters of a class, optionally with a percentage
+    /// chance of its own, such as vowels=80. Classes are letters, vowels,
+    /// consonants, chars:SET, script:NAME and regex:CLASS, the first matching
+    /// target applying to each character
+    #[structopt(long = "target", number_of_values = 1)]
+    targets: Vec<Target>,
+
+    /// End every line with a single LF, even the last one, instead of keeping
+    /// the original line endings
+    #[structopt(long)]
+    unix_line_endings: bool,
+
+    /// Encoding of the input, such as utf-16le, windows-1252, latin1 or
+    /// shift_jis, defaulting to UTF-8. A byte order mark at the start of the
+    /// input takes precedence
+    #[structopt(long, parse(try_from_str = parse_encoding))]
+    input_encoding: Option<&'static Encoding>,
+
+    /// Encoding of the output, defaulting to the encoding of the input.
+    /// Characters it cannot represent are reported as errors
+    #[structopt(long, parse(try_from_str = parse_encoding))]
+    output_encoding: Option<&'static Encoding>,
+
+    /// Split the input into records ended by NUL bytes instead of lines, as
+    /// written by find -print0
+    #[structopt(short = "z", long, conflicts_with_all = &["record-separator", "paragraph"])]
+    null_data: bool,
+
+    /// Split the input into records ended by the given separator, in which
+    /// \0, \n, \r, \t and \\ are escapes. An empty separator splits it into
+    /// paragraphs
+    #[structopt(long, allow_hyphen_values = true, conflicts_with = "paragraph")]
+    record_separator: Option<Separator>,
+
+    /// Only convert the records at an address, a line number such as 10 or a
+    /// regex such as /^ERROR/, or in a range of two such as 10-20 or
+    /// /BEGIN/,/END/. The other records are written unchanged
+    #[structopt(long)]
+    lines: Option<Lines>,
+
+    /// Split the input into paragraphs, ended by one or more blank lines
+    #[structopt(long)]
+    paragraph: bool,
+
+    /// Replace input which is not valid in its encoding with U+FFFD instead of
+    /// failing
+    #[structopt(long, conflicts_with = "bytes")]
+    lossy: bool,
+
+    /// Write input which is not valid in its encoding unchanged instead of
+    /// failing, still converting the text around it. The output encoding
+    /// must be compatible with ASCII
+    #[structopt(long)]
+    bytes: bool,
+
+    /// Case of the first letter when alternating
+    #[structopt(long, default_value = "lower", possible_values = Case::NAMES)]
+    start: Case,
+
+    /// Where alternating goes back to the case of the first letter
+    #[structopt(long, default_value = "never", possible_values = Restart::NAMES)]
+    restart: Restart,
+
+    /// Level of leet substitutions, from 1 (light) to 3 (full)
+    #[structopt(long, default_value = "1", possible_values = Level::NAMES)]
+    level: Level,
+
     /// The text to convert, optional
     content: Option<String>,
 
@@ -65,14 +215,45 @@ pub struct Cli {
     #[structopt(short, long, group = "action")]
     reverse: bool,
 
+    /// Convert the text to title case, capitalising the first letter of
+    /// every word
+    #[structopt(short, long, group = "action")]
+    titlecase: bool,
+
     /// Convert the text to random case
     #[structopt(short, long, group = "action", alias = "random")]
+    #[allow(dead_code)]
     angry: bool,
+
+    /// Strictly alternate the case of the letters in the text
+    #[structopt(long, group = "action")]
+    alternate: bool,
+
+    /// Substitute letters in the text with digits and symbols. To mix it
+    /// with random case, use a pipeline such as "random | leet:level=2"
+    #[structopt(long, group = "action")]
+    leet: bool,
+
+    /// Run the text through a pipeline of convertors, such as
+    /// "random:p=70,s=10 | leet:level=1 | punct"
+    #[structopt(long, group = "action")]
+    pipeline: Option<Pipeline>,
+
+    /// List the convertors available to a pipeline, with their parameters
+    #[structopt(long, group = "action")]
+    list_convertors: bool,
 }
 
 type Convert = Box<dyn Convertor>;
-type Input = Box<dyn Iterator<Item = io::Result<String>>>;
-type Output = LineWriter<Box<dyn Write>>;
+type Input = Decoder<Decompressor<'static>>;
+
+/// Whether `error` was raised by input which is not valid in its encoding.
+fn is_invalid_input(error: &Error) -> bool {
+    error
+        .downcast_ref::<io::Error>()
+        .and_then(io::Error::get_ref)
+        .is_some_and(|inner| inner.is::<InvalidUtf8>() || inner.is::<Malformed>())
+}
 
 #[allow(dead_code)]
 impl Cli {
@@ -85,76 +266,468 @@ impl Cli {
             Conversion::Lowercase
         } else if self.reverse {
             Conversion::Reverse
+        } else if self.titlecase {
+            Conversion::Titlecase
+        } else if self.alternate {
+            Conversion::Alternate(self.start, self.restart)
+        } else if self.leet {
+            Conversion::Leet(self.level, self.percent)
         } else {
             let step = self.step.map(|s| s.unwrap_or(Cli::DEFAULT_STEP));
             Conversion::Random(self.percent, step)
         }
     }
 
+    fn pipeline(&self) -> Pipeline {
+        match &self.pipeline {
+            Some(pipeline) => pipeline.clone(),
+            None => Pipeline::from(self.conversion()),
+        }
+    }
+
+    fn settings(&self) -> Settings {
+        let randomness = if self.stable {
+            Randomness::Stable(self.seed.unwrap_or_default())
+        } else if let Some(seed) = self.seed {
+            Randomness::Seeded(seed)
+        } else {
+            Randomness::Thread
+        };
+
+        Settings {
+            randomness,
+            algorithm: self.algorithm,
+            locale: self.locale.unwrap_or_else(Locale::from_env),
+            locale_from_env: self.locale.is_none(),
+            preserve_length: self.preserve_length,
+            normalization: self.normalize,
+            targets: Targets::new(self.targets.clone()),
+        }
+    }
+
     fn convertor(&self) -> Convert {
-        match self.conversion() {
-            Conversion::Uppercase => SimpleConvertor::uppercase(),
-            Conversion::Lowercase => SimpleConvertor::lowercase(),
-            Conversion::Reverse => SimpleConvertor::reverse(),
-            Conversion::Random(percent, step) => RandomConvertor::new(percent, step),
+        self.pipeline().convertor_with(&self.settings())
+    }
+
+    fn list_convertors(&self) -> Result<()> {
+        let encoding = self.output_encoding.unwrap_or(UTF_8);
+        let compressor = Compressor::new(self.output()?, self.compression(self.output.as_deref()))?;
+        let mut output = Encoder::new(compressor, encoding, false)?;
+        for stage in STAGES {
+            writeln!(output, "{}", stage)?;
+        }
+        output.finish()?.finish()?;
+
+        Ok(())
+    }
+
+    fn separator(&self) -> Separator {
+        if self.null_data {
+            Separator::null()
+        } else if self.paragraph {
+            Separator::Paragraph
+        } else {
+            self.record_separator.clone().unwrap_or_default()
+        }
+    }
+
+    fn utf8_errors(&self) -> Utf8Errors {
+        if self.lossy {
+            Utf8Errors::Lossy
+        } else if self.bytes {
+            Utf8Errors::Bytes
+        } else {
+            Utf8Errors::Strict
+        }
+    }
+
+    fn options(&self) -> driver::Options {
+        driver::Options {
+            separator: self.separator(),
+            utf8_errors: self.utf8_errors(),
+            line_endings: if self.unix_line_endings {
+                LineEndings::Lf
+            } else {
+                LineEndings::Keep
+            },
+            lines: self.lines.clone(),
+        }
+    }
+
+    fn search(&self) -> Search {
+        let mut excludes = self.excludes.clone();
+        if let Some(suffix) = self.suffix.as_ref().or(self.backup()) {
+            // Leave out the conversions or backups written by an earlier run
+            excludes.push(format!("*{}", suffix));
+        }
+
+        Search {
+            recursive: self.recursive,
+            excludes,
+            encoding: self.input_encoding,
         }
     }
 
     fn input(&self) -> Result<Input> {
         if let Some(input) = &self.content {
-            let buffer = Cursor::new(String::from(input));
-            Ok(Box::new(buffer.lines()))
-        } else if let Some(path) = &self.input {
-            let file =
-                File::open(path).with_context(|| format!("could not open file `{:?}`", path))?;
-            let buffer = BufReader::new(file);
-            Ok(Box::new(buffer.lines()))
+            // Text given as an argument is written out as a record of its own
+            let mut record = input.clone().into_bytes();
+            record.extend_from_slice(self.separator().terminator());
+            Decoder::new(Decompressor::new(Cursor::new(record))?, None).map_err(Error::from)
+        } else {
+            let input = Decompressor::new(BufReader::new(io::stdin()))?;
+            Decoder::with_errors(input, self.input_encoding, self.utf8_errors())
+                .context("could not read input")
+        }
+    }
+
+    fn open(&self, path: &Path) -> Result<Input> {
+        let file = File::open(path).with_context(|| format!("could not open file `{:?}`", path))?;
+        Decompressor::new(BufReader::new(file))
+            .and_then(|input| Decoder::with_errors(input, self.input_encoding, self.utf8_errors()))
+            .with_context(|| format!("could not read file `{:?}`", path))
+    }
+
+    fn create(&self, path: &Path) -> Result<File> {
+        let mut options = OpenOptions::new();
+        options.write(true);
+        if self.no_clobber {
+            options.create_new(true);
         } else {
-            let read = io::stdin();
-            let buffer = BufReader::new(read);
-            Ok(Box::new(buffer.lines()))
+            options.create(true).truncate(true);
         }
+
+        options.open(path).map_err(|error| match error.kind() {
+            io::ErrorKind::AlreadyExists => Error::msg(format!(
+                "not overwriting `{}`, which already exists",
+                path.display()
+            )),
+            _ => Error::new(error).context(format!("could not write file `{:?}`", path)),
+        })
     }
 
-    fn output(&self) -> Result<Output> {
+    fn output(&self) -> Result<Box<dyn Write>> {
         if let Some(file) = &self.output {
-            let handle =
-                File::create(file).with_context(|| format!("could not write file `{:?}`", file))?;
-            Ok(LineWriter::new(Box::new(handle)))
+            Ok(Box::new(self.create(file)?))
         } else {
-            let handle = io::stdout();
-            Ok(LineWriter::new(Box::new(handle)))
+            Ok(Box::new(io::stdout()))
         }
     }
 
-    pub fn convert(&self) -> Result<()> {
-        match self.input() {
-            Ok(input) => match self.output() {
-                Ok(output) => match self._convert(self.convertor(), input, output) {
-                    Some(e) => Err(Error::from(e)),
-                    None => Ok(()),
-                },
-                Err(e) => Err(e),
-            },
-            Err(e) => Err(e),
+    /// The path the conversion of an input file is written to, under the
+    /// output directory or with the suffix added.
+    fn destination(&self, path: &Path) -> PathBuf {
+        match &self.out_dir {
+            Some(dir) => files::mirror(dir, path),
+            None => files::with_suffix(path, self.suffix.as_deref().unwrap_or_default()),
         }
     }
 
-    fn _convert(
+    /// The compression of the output written to `path`, given by its
+    /// extension unless it is set.
+    fn compression(&self, path: Option<&Path>) -> Compression {
+        self.compress
+            .unwrap_or_else(|| path.map_or(Compression::None, Compression::from_path))
+    }
+
+    /// Convert `input` and write it to `handle`. When the output `continues`
+    /// after the conversion of an earlier input, no byte order mark is written
+    /// again.
+    fn run<R, W>(
         &self,
-        mut convertor: Convert,
-        input: Input,
-        mut output: Output,
-    ) -> Option<io::Error> {
-        input
-            .filter_map(|s| s.ok())
-            .map(|s| writeln!(output, "{}", convertor.convert(s)))
-            .filter_map(|r| r.err())
-            .nth(0)
+        input: Decoder<R>,
+        handle: W,
+        compression: Compression,
+        continues: bool,
+    ) -> Result<()>
+    where
+        R: BufRead,
+        W: Write,
+    {
+        let encoding = self.output_encoding.unwrap_or_else(|| input.encoding());
+        if self.bytes && !encoding.is_ascii_compatible() {
+            return Err(Error::msg(format!(
+                "--bytes cannot write invalid input unchanged in {}, which is not compatible with ASCII",
+                encoding.name()
+            )));
+        }
+        let compressor = Compressor::new(handle, compression).context("could not write output")?;
+        let encoder = if continues {
+            Encoder::continuing(compressor, encoding)
+        } else {
+            Encoder::new(compressor, encoding, input.has_bom()).context("could not write output")?
+        };
+        let mut output = LineWriter::new(encoder);
+
+        driver::convert_with(
+            self.convertor().as_mut(),
+            input,
+            &mut output,
+            &self.options(),
+        )?;
+        output
+            .into_inner()
+            .map_err(IntoInnerError::into_error)?
+            .finish()?
+            .finish()?;
+
+        Ok(())
+    }
+
+    /// Rewrite the file at `path` with its conversion, compressed as it was
+    /// unless the compression is set.
+    fn rewrite(&self, path: &Path, backup: Option<&str>) -> Result<()> {
+        files::rewrite(path, backup, |file| {
+            let input = self.open(path)?;
+            let compression = self
+                .compress
+                .unwrap_or_else(|| input.get_ref().compression());
+            self.run(input, file, compression, false)
+        })
+    }
+
+    /// The suffix of the backups kept when rewriting files in place.
+    fn backup(&self) -> Option<&String> {
+        self.in_place.as_ref().and_then(Option::as_ref)
+    }
+
+    fn convert_file(&self, path: &Path) -> Result<()> {
+        if self.in_place.is_some() {
+            return self.rewrite(path, self.backup().map(String::as_str));
+        }
+
+        let input = self.open(path)?;
+        let destination = self.destination(path);
+        if files::is_same_file(path, &destination) {
+            return Err(Error::msg(format!(
+                "`{}` would be written over itself",
+                path.display()
+            )));
+        }
+        if let Some(dir) = destination.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("could not create directory `{:?}`", dir))?;
+        }
+        let file = self.create(&destination)?;
+
+        self.run(input, file, self.compression(Some(&destination)), false)
+    }
+
+    fn convert_bytes(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut converted = Vec::new();
+        let input = Decoder::with_errors(data, self.input_encoding, self.utf8_errors())?;
+        self.run(input, &mut converted, Compression::None, false)?;
+
+        Ok(converted)
+    }
+
+    /// Convert the members of the archive at `path`, each with its own
+    /// convertor, writing the archive to the output. A tar archive may be
+    /// compressed as a whole, while a zip archive can only be written to an
+    /// output file.
+    fn convert_archive(&self, path: &Path) -> Result<()> {
+        if let Some(output) = &self.output {
+            if files::is_same_file(path, output) {
+                return Err(Error::msg(format!(
+                    "the output `{}` is also the archive, and would be overwritten before it is read",
+                    output.display()
+                )));
+            }
+        }
+
+        let members = Members::new(&self.members, &self.excludes, self.input_encoding)?;
+        let file = File::open(path).with_context(|| format!("could not open file `{:?}`", path))?;
+        let mut input = BufReader::new(file);
+
+        let convert = |name: &str, data: &[u8]| self.convert_member(name, data);
+        match archive::Format::detect(input.fill_buf()?) {
+            archive::Format::Zip => {
+                let output = self.output.as_deref().ok_or_else(|| {
+                    Error::msg("a zip archive can only be written to a file given with --output")
+                })?;
+                files::create(output, self.no_clobber, |file| {
+                    archive::convert_zip(input, file, &members, convert)?;
+                    Ok(())
+                })?;
+            }
+            archive::Format::Tar => {
+                let compression = self.compression(self.output.as_deref());
+                let write = |output: Box<dyn Write + '_>| -> Result<()> {
+                    let output = Compressor::new(output, compression)?;
+                    archive::convert_tar(Decompressor::new(input)?, output, &members, convert)?
+                        .finish()?;
+                    Ok(())
+                };
+                match &self.output {
+                    Some(output) => {
+                        files::create(output, self.no_clobber, |file| write(Box::new(file)))?
+                    }
+                    None => write(Box::new(io::stdout()))?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Convert the member of an archive called `name`, or leave it to be
+    /// copied unchanged, with a warning, when it is not valid in its encoding.
+    fn convert_member(&self, name: &str, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.convert_bytes(data) {
+            Ok(converted) => Ok(Some(converted)),
+            Err(error) if is_invalid_input(&error) => {
+                eprintln!("angry: copying member `{}` unchanged: {:#}", name, error);
+                Ok(None)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Check that no input file is written over by the conversion of another,
+    /// and that no two inputs are written to the same destination, before any
+    /// file is converted.
+    fn check_destinations(&self, inputs: &[PathBuf]) -> Result<()> {
+        if self.out_dir.is_none() && self.suffix.is_none() {
+            return Ok(());
+        }
+
+        let ids: HashMap<FileId, &PathBuf> =
+            inputs.iter().map(|path| (FileId::of(path), path)).collect();
+        let mut destinations = HashMap::new();
+        for path in inputs {
+            let destination = self.destination(path);
+            let id = FileId::of(&destination);
+            if let Some(input) = ids.get(&id) {
+                return Err(Error::msg(format!(
+                    "the conversion of `{}` would be written over the input `{}`",
+                    path.display(),
+                    input.display()
+                )));
+            } else if let Some(other) = destinations.insert(id, path) {
+                return Err(Error::msg(format!(
+                    "`{}` and `{}` would both be written to `{}`",
+                    other.display(),
+                    path.display(),
+                    destination.display()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Convert the input files, each with its own convertor so that a seed
+    /// gives every file the conversion it would have on its own.
+    ///
+    /// Files written to a destination of their own are converted in parallel,
+    /// while those written to the output are converted in order.
+    fn convert_files(&self) -> Result<()> {
+        let found = files::find(&self.inputs, &self.search())?;
+        for path in &found.binary {
+            eprintln!("angry: skipping binary file `{}`", path.display());
+        }
+
+        let output = self.output.as_deref();
+        if let Some(path) = output.and_then(|output| {
+            found
+                .files
+                .iter()
+                .find(|path| files::is_same_file(path, output))
+        }) {
+            if found.files.len() > 1 {
+                return Err(Error::msg(format!(
+                    "the output `{}` is also an input, and would be overwritten before it is read",
+                    path.display()
+                )));
+            } else if self.no_clobber {
+                return Err(Error::msg(format!(
+                    "not overwriting `{}`, which already exists",
+                    path.display()
+                )));
+            }
+
+            // Rewrite the only input in place instead of truncating it first
+            return self.rewrite(path, None);
+        }
+
+        if self.in_place.is_none() {
+            self.check_destinations(&found.files)?;
+        }
+
+        let separate = self.in_place.is_some() || self.out_dir.is_some() || self.suffix.is_some();
+        let results: Vec<Result<()>> = if separate {
+            found
+                .files
+                .par_iter()
+                .map(|path| self.convert_file(path))
+                .collect()
+        } else {
+            let mut output = self.output()?;
+            let compression = self.compression(self.output.as_deref());
+            let mut continues = false;
+            found
+                .files
+                .iter()
+                .map(|path| {
+                    let input = self.open(path)?;
+                    let result = self.run(input, &mut output, compression, continues);
+                    continues = true;
+                    result
+                })
+                .collect()
+        };
+
+        let mut failures = 0;
+        for (path, result) in found.files.iter().zip(results) {
+            if let Err(error) = result {
+                eprintln!("angry: could not convert `{}`: {:#}", path.display(), error);
+                failures += 1;
+            }
+        }
+
+        match failures {
+            0 => Ok(()),
+            _ => Err(Error::msg(format!(
+                "{} of {} files could not be converted",
+                failures,
+                found.files.len()
+            ))),
+        }
+    }
+
+    pub fn convert(&self) -> Result<()> {
+        if self.list_convertors {
+            return self.list_convertors();
+        } else if let Some(path) = &self.archive {
+            return self.convert_archive(path);
+        } else if self.content.is_none() && !self.inputs.is_empty() {
+            return self.convert_files();
+        } else if self.in_place.is_some() {
+            return Err(Error::msg(
+                "--in-place needs input files given with --input",
+            ));
+        }
+
+        if let Some(output) = &self.output {
+            if self.content.is_none() && files::is_stdin(output) {
+                return Err(Error::msg(format!(
+                    "the output `{}` is also the input, and would be overwritten before it is read",
+                    output.display()
+                )));
+            }
+        }
+
+        let compression = self.compression(self.output.as_deref());
+        self.run(self.input()?, self.output()?, compression, false)
     }
 }
 
 #[cfg(test)]
+#[allow(
+    clippy::bool_assert_comparison,
+    clippy::needless_borrows_for_generic_args,
+    clippy::redundant_pattern_matching
+)]
 mod tests {
     use super::*;
 
@@ -240,6 +813,75 @@ mod tests {
         assert_eq!(cli.percent, 90);
     }
 
+    #[test]
+    fn with_seed_arg() {
+        assert_eq!(
+            Cli::from_iter(&["test", "-a", "--seed", "42"]).seed,
+            Some(42)
+        );
+    }
+
+    #[test]
+    fn with_locale_arg() {
+        assert_eq!(
+            Cli::from_iter(&["test", "-u", "--locale", "tr_TR.UTF-8"]).locale,
+            Some(Locale::Turkish)
+        );
+    }
+
+    #[test]
+    fn with_seed_settings() {
+        let settings = Cli::from_iter(&["test", "-a", "--seed", "42", "--locale", "lt"]).settings();
+
+        assert_eq!(settings.randomness, Randomness::Seeded(42));
+        assert_eq!(settings.locale, Locale::Lithuanian);
+    }
+
+    #[test]
+    fn with_target_args() {
+        let settings = Cli::from_iter(&[
+            "test",
+            "-a",
+            "--target",
+            "vowels=80",
+            "--target",
+            "consonants=30",
+        ])
+        .settings();
+
+        assert_eq!(
+            settings.targets,
+            Targets::new(vec![
+                "vowels=80".parse().unwrap(),
+                "consonants=30".parse().unwrap()
+            ])
+        );
+    }
+
+    #[test]
+    fn with_stable_arg() {
+        assert!(Cli::from_iter(&["test", "-a", "--stable"]).stable);
+    }
+
+    #[test]
+    fn with_algorithm_default() {
+        assert_eq!(Cli::from_iter(&["test", "-a"]).algorithm, None);
+    }
+
+    #[test]
+    fn with_unknown_algorithm() {
+        assert!(Cli::clap()
+            .get_matches_from_safe(&["test", "-a", "--algorithm", "v0"])
+            .is_err());
+    }
+
+    #[test]
+    fn with_unknown_leet_level() {
+        assert!(Cli::clap()
+            .get_matches_from_safe(&["test", "--leet", "--level", "4"])
+            .is_err());
+    }
+
     #[test]
     fn without_required_arg() {
         assert!(Cli::clap().get_matches_from_safe(&["test"]).is_err());
@@ -276,6 +918,14 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn titlecase_conversion() {
+        assert!(matches!(
+            Cli::from_iter(&["test", "--titlecase"]).conversion(),
+            Conversion::Titlecase
+        ));
+    }
+
     #[test]
     fn conversion_with_random() {
         assert!(matches!(
@@ -308,6 +958,85 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn conversion_with_alternate() {
+        assert!(matches!(
+            Cli::from_iter(&["test", "--alternate"]).conversion(),
+            Conversion::Alternate(Case::Lower, Restart::Never)
+        ));
+    }
+
+    #[test]
+    fn conversion_with_alternate_start_and_restart() {
+        assert!(matches!(
+            Cli::from_iter(&[
+                "test",
+                "--alternate",
+                "--start",
+                "upper",
+                "--restart",
+                "word"
+            ])
+            .conversion(),
+            Conversion::Alternate(Case::Upper, Restart::Word)
+        ));
+    }
+
+    #[test]
+    fn with_alternate_and_random() {
+        assert!(Cli::clap()
+            .get_matches_from_safe(&["test", "--alternate", "--angry"])
+            .is_err());
+    }
+
+    #[test]
+    fn conversion_with_leet() {
+        assert!(matches!(
+            Cli::from_iter(&["test", "--leet"]).conversion(),
+            Conversion::Leet(Level::Light, 50)
+        ));
+    }
+
+    #[test]
+    fn conversion_with_leet_level_and_percent() {
+        assert!(matches!(
+            Cli::from_iter(&["test", "--leet", "--level", "3", "-p100"]).conversion(),
+            Conversion::Leet(Level::Full, 100)
+        ));
+    }
+
+    #[test]
+    fn pipeline_with_pipeline_arg() {
+        assert_eq!(
+            Cli::from_iter(&["test", "--pipeline", "upper | leet:level=2"])
+                .pipeline()
+                .conversions(),
+            &[Conversion::Uppercase, Conversion::Leet(Level::Medium, 50)]
+        );
+    }
+
+    #[test]
+    fn pipeline_without_pipeline_arg() {
+        assert_eq!(
+            Cli::from_iter(&["test", "--lowercase"])
+                .pipeline()
+                .conversions(),
+            &[Conversion::Lowercase]
+        );
+    }
+
+    #[test]
+    fn with_invalid_pipeline() {
+        assert!(Cli::clap()
+            .get_matches_from_safe(&["test", "--pipeline", "upper | shout"])
+            .is_err());
+    }
+
+    #[test]
+    fn with_list_convertors() {
+        assert!(Cli::from_iter(&["test", "--list-convertors"]).list_convertors);
+    }
+
     #[test]
     fn with_random_and_percent_out_of_bound() {
         assert!(Cli::clap()
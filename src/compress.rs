@@ -0,0 +1,257 @@
+//! Read and write compressed text as a stream, the compression of the input
+//! being detected from its first bytes.
+
+use {
+    anyhow::{Error, Result},
+    flate2::{bufread::MultiGzDecoder, write::GzEncoder},
+    std::{
+        fmt::{self, Display, Formatter},
+        io::{self, BufRead, BufReader, Cursor, Read, Write},
+        path::Path,
+        str::FromStr,
+    },
+};
+
+/// The magic bytes at the start of a gzip stream.
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+
+/// The magic bytes at the start of a Zstandard frame.
+const ZSTD_MAGIC: &[u8] = b"\x28\xb5\x2f\xfd";
+
+/// A compression format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    /// Not compressed.
+    #[default]
+    None,
+    /// The gzip format of `.gz` files.
+    Gzip,
+    /// The Zstandard format of `.zst` files.
+    Zstd,
+}
+
+impl Compression {
+    /// The names of all the formats.
+    pub const NAMES: &'static [&'static str] = &["none", "gzip", "zstd"];
+
+    /// The format of a stream starting with `start`.
+    pub fn detect(start: &[u8]) -> Self {
+        if start.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if start.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    /// The format given by the extension of `path`, `.gz` or `.zst`.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(Compression::None),
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(Error::msg(format!(
+                "unknown compression `{}`, expected one of: {}",
+                s,
+                Compression::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Compression::None => write!(f, "none"),
+            Compression::Gzip => write!(f, "gzip"),
+            Compression::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+/// A reader which decompresses its input, if it is compressed.
+pub struct Decompressor<'a> {
+    compression: Compression,
+    reader: Box<dyn BufRead + 'a>,
+}
+
+impl<'a> Decompressor<'a> {
+    /// Decompress `input` in the format detected from its first bytes, or
+    /// pass it on as it is when it is not compressed.
+    pub fn new<R: BufRead + 'a>(mut input: R) -> io::Result<Self> {
+        let mut start = Vec::with_capacity(ZSTD_MAGIC.len());
+        (&mut input)
+            .take(ZSTD_MAGIC.len() as u64)
+            .read_to_end(&mut start)?;
+
+        let compression = Compression::detect(&start);
+        let input = Cursor::new(start).chain(input);
+        let reader: Box<dyn BufRead + 'a> = match compression {
+            Compression::None => Box::new(input),
+            Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(input))),
+            Compression::Zstd => Box::new(BufReader::new(
+                zstd::stream::read::Decoder::with_buffer(input)?,
+            )),
+        };
+
+        Ok(Decompressor {
+            compression,
+            reader,
+        })
+    }
+
+    /// The format of the input.
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+}
+
+impl Read for Decompressor<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl BufRead for Decompressor<'_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.reader.consume(amount)
+    }
+}
+
+/// A writer which compresses what is written to it. Once everything is
+/// written, the compressor is [finished](Compressor::finish) to end the
+/// compressed stream.
+pub enum Compressor<W: Write> {
+    None(W),
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+}
+
+impl<W: Write> Compressor<W> {
+    /// Compress to `output` in the given format, at its default level.
+    pub fn new(output: W, compression: Compression) -> io::Result<Self> {
+        Ok(match compression {
+            Compression::None => Compressor::None(output),
+            Compression::Gzip => {
+                Compressor::Gzip(GzEncoder::new(output, flate2::Compression::default()))
+            }
+            Compression::Zstd => Compressor::Zstd(zstd::stream::write::Encoder::new(output, 0)?),
+        })
+    }
+
+    /// End the compressed stream, and return the underlying writer.
+    pub fn finish(self) -> io::Result<W> {
+        let mut output = match self {
+            Compressor::None(output) => output,
+            Compressor::Gzip(encoder) => encoder.finish()?,
+            Compressor::Zstd(encoder) => encoder.finish()?,
+        };
+        output.flush()?;
+
+        Ok(output)
+    }
+}
+
+impl<W: Write> Write for Compressor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Compressor::None(output) => output.write(buf),
+            Compressor::Gzip(encoder) => encoder.write(buf),
+            Compressor::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Compressor::None(output) => output.flush(),
+            Compressor::Gzip(encoder) => encoder.flush(),
+            Compressor::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compress(text: &str, compression: Compression) -> Vec<u8> {
+        let mut compressor = Compressor::new(Vec::new(), compression).unwrap();
+        compressor.write_all(text.as_bytes()).unwrap();
+
+        compressor.finish().unwrap()
+    }
+
+    fn decompress(bytes: &[u8]) -> (String, Compression) {
+        let mut decompressor = Decompressor::new(bytes).unwrap();
+        let mut text = String::new();
+        decompressor.read_to_string(&mut text).unwrap();
+
+        (text, decompressor.compression())
+    }
+
+    #[test]
+    fn round_trip() {
+        for &compression in &[Compression::None, Compression::Gzip, Compression::Zstd] {
+            let compressed = compress("some text\n", compression);
+
+            assert_eq!(Compression::detect(&compressed), compression);
+            assert_eq!(
+                decompress(&compressed),
+                (String::from("some text\n"), compression)
+            );
+        }
+    }
+
+    #[test]
+    fn decompress_concatenated_gzip() {
+        let mut compressed = compress("one\n", Compression::Gzip);
+        compressed.extend(compress("two\n", Compression::Gzip));
+
+        assert_eq!(decompress(&compressed).0, "one\ntwo\n");
+    }
+
+    #[test]
+    fn decompress_short_input() {
+        assert_eq!(decompress(b"a"), (String::from("a"), Compression::None));
+        assert_eq!(decompress(b""), (String::new(), Compression::None));
+    }
+
+    #[test]
+    fn compression_from_path() {
+        assert_eq!(
+            Compression::from_path(Path::new("a.txt.gz")),
+            Compression::Gzip
+        );
+        assert_eq!(
+            Compression::from_path(Path::new("a.zst")),
+            Compression::Zstd
+        );
+        assert_eq!(
+            Compression::from_path(Path::new("a.txt")),
+            Compression::None
+        );
+    }
+
+    #[test]
+    fn compression_from_str() {
+        assert_eq!("zstd".parse::<Compression>().unwrap(), Compression::Zstd);
+        assert!("rar".parse::<Compression>().is_err());
+    }
+}
@@ -0,0 +1,241 @@
+use {
+    crate::convert::{
+        casing::{self, Context, Rules},
+        convert_char_as_grapheme, Convertor, Settings,
+    },
+    std::{
+        fmt::{self, Debug, Display, Formatter},
+        iter::Iterator,
+        str::FromStr,
+    },
+};
+
+/// The case given to a letter by an [`AlternatingConvertor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Case {
+    #[default]
+    Lower,
+    Upper,
+}
+
+impl Case {
+    /// The names of all the cases.
+    pub const NAMES: &'static [&'static str] = &["lower", "upper"];
+
+    fn toggle(self) -> Self {
+        match self {
+            Case::Lower => Case::Upper,
+            Case::Upper => Case::Lower,
+        }
+    }
+}
+
+impl FromStr for Case {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "lower" => Ok(Case::Lower),
+            "upper" => Ok(Case::Upper),
+            _ => Err(anyhow::Error::msg(format!(
+                "unknown case `{}`, expected one of: {}",
+                s,
+                Case::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+impl Display for Case {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Case::Lower => write!(f, "lower"),
+            Case::Upper => write!(f, "upper"),
+        }
+    }
+}
+
+/// When an [`AlternatingConvertor`] goes back to its starting case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Restart {
+    /// Keep alternating across the whole input.
+    #[default]
+    Never,
+    /// Restart after any whitespace, and at the start of every line.
+    Word,
+    /// Restart at the start of every line.
+    Line,
+    /// Restart after a `.`, `!` or `?`.
+    Sentence,
+}
+
+impl Restart {
+    /// The names of all the restart points.
+    pub const NAMES: &'static [&'static str] = &["never", "word", "line", "sentence"];
+}
+
+impl FromStr for Restart {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "never" => Ok(Restart::Never),
+            "word" => Ok(Restart::Word),
+            "line" => Ok(Restart::Line),
+            "sentence" => Ok(Restart::Sentence),
+            _ => Err(anyhow::Error::msg(format!(
+                "unknown restart `{}`, expected one of: {}",
+                s,
+                Restart::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+impl Display for Restart {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Restart::Never => write!(f, "never"),
+            Restart::Word => write!(f, "word"),
+            Restart::Line => write!(f, "line"),
+            Restart::Sentence => write!(f, "sentence"),
+        }
+    }
+}
+
+/// A convertor which strictly alternates the case of letters, "sPoNgEbOb"
+/// style.
+///
+/// Only letters with a case are counted, so spaces, digits and punctuation
+/// never break the rhythm.
+#[derive(Debug)]
+pub struct AlternatingConvertor {
+    start: Case,
+    restart: Restart,
+    next: Case,
+    rules: Rules,
+}
+
+impl AlternatingConvertor {
+    /// Create an alternating convertor giving `start` to the first letter,
+    /// and going back to it at every `restart` point.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(start: Case, restart: Restart) -> Box<dyn Convertor> {
+        Box::new(AlternatingConvertor {
+            start,
+            restart,
+            next: start,
+            rules: Rules::default(),
+        })
+    }
+
+    fn restarts_after(&self, c: char) -> bool {
+        match self.restart {
+            Restart::Word => c.is_whitespace(),
+            Restart::Sentence => matches!(c, '.' | '!' | '?'),
+            _ => false,
+        }
+    }
+}
+
+impl Convertor for AlternatingConvertor {
+    fn reset(&mut self, _record: &str) {
+        if matches!(self.restart, Restart::Word | Restart::Line) {
+            self.next = self.start;
+        }
+    }
+
+    fn configure(&mut self, settings: &Settings) {
+        self.rules = settings.rules();
+    }
+
+    fn convert_grapheme(&mut self, g: &str, context: &Context) -> String {
+        match g.chars().next() {
+            Some(c) if casing::is_cased(c) => {
+                let case = self.next;
+                self.next = case.toggle();
+
+                match case {
+                    Case::Lower => casing::lowercase_grapheme(g, context, self.rules),
+                    Case::Upper => casing::uppercase_grapheme(g, context, self.rules),
+                }
+            }
+            Some(c) => {
+                if self.restarts_after(c) {
+                    self.next = self.start;
+                }
+
+                String::from(g)
+            }
+            None => String::new(),
+        }
+    }
+
+    fn convert_char_in(&mut self, c: char, context: &Context) -> Box<dyn Iterator<Item = char>> {
+        convert_char_as_grapheme(self, c, context)
+    }
+
+    fn convert_char(&mut self, c: char) -> Box<dyn Iterator<Item = char>> {
+        self.convert_char_in(c, &Context::none())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alternate_convert() {
+        let mut c = AlternatingConvertor::new(Case::Lower, Restart::Never);
+
+        assert_eq!(c.convert(String::from("SpongeBob")), "sPoNgEbOb");
+    }
+
+    #[test]
+    fn alternate_convert_skips_non_letters() {
+        let mut c = AlternatingConvertor::new(Case::Lower, Restart::Never);
+
+        assert_eq!(c.convert(String::from("it's a test")), "iT's A tEsT");
+    }
+
+    #[test]
+    fn alternate_convert_from_upper() {
+        let mut c = AlternatingConvertor::new(Case::Upper, Restart::Never);
+
+        assert_eq!(c.convert(String::from("simple")), "SiMpLe");
+    }
+
+    #[test]
+    fn alternate_convert_restart_word() {
+        let mut c = AlternatingConvertor::new(Case::Lower, Restart::Word);
+
+        assert_eq!(c.convert(String::from("one two three")), "oNe tWo tHrEe");
+    }
+
+    #[test]
+    fn alternate_convert_restart_sentence() {
+        let mut c = AlternatingConvertor::new(Case::Lower, Restart::Sentence);
+
+        assert_eq!(c.convert(String::from("one. two! six")), "oNe. tWo! sIx");
+    }
+
+    #[test]
+    fn alternate_convert_restart_line() {
+        let mut c = AlternatingConvertor::new(Case::Lower, Restart::Line);
+
+        c.reset("one");
+        assert_eq!(c.convert(String::from("one")), "oNe");
+        c.reset("one");
+        assert_eq!(c.convert(String::from("one")), "oNe");
+    }
+
+    #[test]
+    fn alternate_convert_never_restarts_line() {
+        let mut c = AlternatingConvertor::new(Case::Lower, Restart::Never);
+
+        c.reset("one");
+        assert_eq!(c.convert(String::from("one")), "oNe");
+        c.reset("one");
+        assert_eq!(c.convert(String::from("one")), "OnE");
+    }
+}
@@ -0,0 +1,611 @@
+//! Case mappings which follow the conditional rules of Unicode
+//! SpecialCasing, looking at the characters around the one being mapped.
+
+use {
+    std::{
+        fmt::{self, Display, Formatter},
+        iter::{empty, once, Iterator},
+        str::FromStr,
+    },
+    unicode_normalization::char::canonical_combining_class,
+    unicode_properties::{GeneralCategory, UnicodeGeneralCategory},
+};
+
+/// Canonical combining class of marks placed above their base character.
+const ABOVE: u8 = 230;
+
+/// A language whose case mappings are tailored by Unicode SpecialCasing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    /// The language-independent mappings.
+    #[default]
+    Root,
+    /// Turkish, with a dotted and a dotless `i`.
+    Turkish,
+    /// Azeri, with a dotted and a dotless `i`.
+    Azeri,
+    /// Lithuanian, which keeps the dot on an accented `i`.
+    Lithuanian,
+}
+
+impl Locale {
+    /// The locale of the environment, from `LC_ALL`, `LC_CTYPE` or `LANG`,
+    /// or the root locale when it is not a valid one.
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_default()
+    }
+}
+
+impl FromStr for Locale {
+    type Err = anyhow::Error;
+
+    /// Parse a locale such as `tr`, `az-Latn` or `lt_LT.UTF-8`. Languages
+    /// without tailored case mappings, such as `en_US.UTF-8`, `C` or `root`,
+    /// give the root locale, while anything which is not a language code is
+    /// an error.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let language = s.split(['_', '-', '.', '@']).next().unwrap_or_default();
+
+        match language.to_ascii_lowercase().as_str() {
+            "tr" => Ok(Locale::Turkish),
+            "az" => Ok(Locale::Azeri),
+            "lt" => Ok(Locale::Lithuanian),
+            "c" | "posix" | "root" | "und" => Ok(Locale::Root),
+            language
+                if (2..=3).contains(&language.len())
+                    && language.bytes().all(|b| b.is_ascii_lowercase()) =>
+            {
+                Ok(Locale::Root)
+            }
+            _ => Err(anyhow::Error::msg(format!(
+                "unknown locale `{}`, expected a language code such as tr, az or lt",
+                s
+            ))),
+        }
+    }
+}
+
+impl Display for Locale {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Locale::Root => write!(f, "root"),
+            Locale::Turkish => write!(f, "tr"),
+            Locale::Azeri => write!(f, "az"),
+            Locale::Lithuanian => write!(f, "lt"),
+        }
+    }
+}
+
+/// The rules followed when mapping grapheme clusters to another case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rules {
+    /// The language whose tailored mappings are used.
+    pub locale: Locale,
+    /// Map every character to exactly one character, so the converted text
+    /// has as many characters as the original.
+    pub preserve_length: bool,
+}
+
+impl From<Locale> for Rules {
+    fn from(locale: Locale) -> Self {
+        Rules {
+            locale,
+            ..Rules::default()
+        }
+    }
+}
+
+/// The text around a character being converted, used to evaluate the
+/// conditions of context-sensitive case mappings.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Context<'a> {
+    text: &'a str,
+    index: usize,
+}
+
+impl<'a> Context<'a> {
+    /// The context of the character starting at byte `index` of `text`.
+    pub fn new(text: &'a str, index: usize) -> Self {
+        Context { text, index }
+    }
+
+    /// A context with nothing around the character.
+    pub fn none() -> Self {
+        Context::default()
+    }
+
+    /// The context of the character `offset` bytes after this one.
+    pub fn offset(&self, offset: usize) -> Self {
+        Context::new(self.text, self.index + offset)
+    }
+
+    /// The text before the character.
+    pub fn before(&self) -> &'a str {
+        self.text.get(..self.index).unwrap_or_default()
+    }
+
+    /// The text after the character.
+    pub fn after(&self) -> &'a str {
+        let mut chars = self.text.get(self.index..).unwrap_or_default().chars();
+        chars.next();
+        chars.as_str()
+    }
+
+    /// The character is preceded by a cased letter and not followed by one,
+    /// ignoring any case-ignorable characters in between.
+    pub fn final_sigma(&self) -> bool {
+        let before = self
+            .before()
+            .chars()
+            .rev()
+            .find(|c| !is_case_ignorable(*c))
+            .is_some_and(is_cased);
+        let after = self
+            .after()
+            .chars()
+            .find(|c| !is_case_ignorable(*c))
+            .is_some_and(is_cased);
+
+        before && !after
+    }
+
+    /// There is a soft-dotted character before this one, with no starter or
+    /// mark above in between.
+    pub fn after_soft_dotted(&self) -> bool {
+        self.before()
+            .chars()
+            .rev()
+            .find(|c| is_starter_or_above(*c))
+            .is_some_and(is_soft_dotted)
+    }
+
+    /// The character is followed by a mark above, with no starter in between.
+    pub fn more_above(&self) -> bool {
+        self.after()
+            .chars()
+            .find(|c| is_starter_or_above(*c))
+            .is_some_and(|c| canonical_combining_class(c) == ABOVE)
+    }
+
+    /// The character is followed by a combining dot above, with no starter or
+    /// other mark above in between.
+    pub fn before_dot(&self) -> bool {
+        self.after()
+            .chars()
+            .find(|c| is_starter_or_above(*c))
+            .is_some_and(|c| c == '\u{307}')
+    }
+
+    /// There is an uppercase `I` before this character, with no starter or
+    /// mark above in between.
+    pub fn after_i(&self) -> bool {
+        self.before()
+            .chars()
+            .rev()
+            .find(|c| is_starter_or_above(*c))
+            .is_some_and(|c| c == 'I')
+    }
+}
+
+fn is_starter_or_above(c: char) -> bool {
+    matches!(canonical_combining_class(c), 0 | ABOVE)
+}
+
+/// Whether `c` is a titlecase letter, such as `ǅ`.
+pub fn is_titlecase(c: char) -> bool {
+    c.general_category() == GeneralCategory::TitlecaseLetter
+}
+
+/// Whether `c` has a case, the Unicode `Cased` property.
+pub fn is_cased(c: char) -> bool {
+    c.is_lowercase() || c.is_uppercase() || is_titlecase(c)
+}
+
+/// Whether `c` is ignored when looking for the cased letters around another,
+/// the Unicode `Case_Ignorable` property.
+pub fn is_case_ignorable(c: char) -> bool {
+    matches!(
+        c,
+        // Word_Break=MidLetter, MidNumLet and Single_Quote
+        '\'' | '.'
+            | ':'
+            | '·'
+            | '\u{387}'
+            | '\u{55f}'
+            | '\u{5f4}'
+            | '\u{2018}'
+            | '\u{2019}'
+            | '\u{2024}'
+            | '\u{2027}'
+            | '\u{fe13}'
+            | '\u{fe52}'
+            | '\u{fe55}'
+            | '\u{ff07}'
+            | '\u{ff0e}'
+            | '\u{ff1a}'
+    ) || matches!(
+        c.general_category(),
+        GeneralCategory::NonspacingMark
+            | GeneralCategory::EnclosingMark
+            | GeneralCategory::Format
+            | GeneralCategory::ModifierLetter
+            | GeneralCategory::ModifierSymbol
+    )
+}
+
+/// Whether `c` loses its dot when an accent is placed above it, the Unicode
+/// `Soft_Dotted` property.
+pub fn is_soft_dotted(c: char) -> bool {
+    matches!(
+        c,
+        'i' | 'j'
+            | '\u{12f}'
+            | '\u{249}'
+            | '\u{268}'
+            | '\u{29d}'
+            | '\u{2b2}'
+            | '\u{3f3}'
+            | '\u{456}'
+            | '\u{458}'
+            | '\u{1d62}'
+            | '\u{1d96}'
+            | '\u{1da4}'
+            | '\u{1da8}'
+            | '\u{1e2d}'
+            | '\u{1ecb}'
+            | '\u{2071}'
+            | '\u{2148}'
+            | '\u{2149}'
+            | '\u{2c7c}'
+    )
+}
+
+/// Map `c` to lowercase, applying the conditional mappings of SpecialCasing
+/// for its context and locale.
+pub fn lowercase(c: char, context: &Context, locale: Locale) -> Box<dyn Iterator<Item = char>> {
+    use Locale::*;
+
+    match (locale, c) {
+        (Lithuanian, 'I') if context.more_above() => Box::new("i\u{307}".chars()),
+        (Lithuanian, 'J') if context.more_above() => Box::new("j\u{307}".chars()),
+        (Lithuanian, '\u{12e}') if context.more_above() => Box::new("\u{12f}\u{307}".chars()),
+        (Lithuanian, '\u{cc}') => Box::new("i\u{307}\u{300}".chars()),
+        (Lithuanian, '\u{cd}') => Box::new("i\u{307}\u{301}".chars()),
+        (Lithuanian, '\u{128}') => Box::new("i\u{307}\u{303}".chars()),
+        (Turkish | Azeri, '\u{130}') => Box::new(once('i')),
+        (Turkish | Azeri, '\u{307}') if context.after_i() => Box::new(empty()),
+        (Turkish | Azeri, 'I') if !context.before_dot() => Box::new(once('ı')),
+        (_, 'Σ') if context.final_sigma() => Box::new(once('ς')),
+        _ => Box::new(c.to_lowercase()),
+    }
+}
+
+/// Map `c` to uppercase, applying the conditional mappings of SpecialCasing
+/// for its context and locale.
+pub fn uppercase(c: char, context: &Context, locale: Locale) -> Box<dyn Iterator<Item = char>> {
+    use Locale::*;
+
+    match (locale, c) {
+        (Lithuanian, '\u{307}') if context.after_soft_dotted() => Box::new(empty()),
+        (Turkish | Azeri, 'i') => Box::new(once('\u{130}')),
+        _ => Box::new(c.to_uppercase()),
+    }
+}
+
+/// The titlecase mappings which differ from the uppercase ones, from
+/// UnicodeData and SpecialCasing.
+fn title_form(c: char) -> Option<&'static str> {
+    Some(match c {
+        '\u{1c4}'..='\u{1c6}' => "\u{1c5}",
+        '\u{1c7}'..='\u{1c9}' => "\u{1c8}",
+        '\u{1ca}'..='\u{1cc}' => "\u{1cb}",
+        '\u{1f1}'..='\u{1f3}' => "\u{1f2}",
+        'ß' => "Ss",
+        '\u{587}' => "\u{535}\u{582}",
+        'ﬀ' => "Ff",
+        'ﬁ' => "Fi",
+        'ﬂ' => "Fl",
+        'ﬃ' => "Ffi",
+        'ﬄ' => "Ffl",
+        'ﬅ' | 'ﬆ' => "St",
+        '\u{fb13}' => "\u{544}\u{576}",
+        '\u{fb14}' => "\u{544}\u{565}",
+        '\u{fb15}' => "\u{544}\u{56b}",
+        '\u{fb16}' => "\u{54e}\u{576}",
+        '\u{fb17}' => "\u{544}\u{56d}",
+        '\u{1fb2}' => "\u{1fba}\u{345}",
+        '\u{1fb4}' => "\u{386}\u{345}",
+        '\u{1fb7}' => "\u{391}\u{342}\u{345}",
+        '\u{1fc2}' => "\u{1fca}\u{345}",
+        '\u{1fc4}' => "\u{389}\u{345}",
+        '\u{1fc7}' => "\u{397}\u{342}\u{345}",
+        '\u{1ff2}' => "\u{1ffa}\u{345}",
+        '\u{1ff4}' => "\u{38f}\u{345}",
+        '\u{1ff7}' => "\u{3a9}\u{342}\u{345}",
+        _ => return None,
+    })
+}
+
+/// Whether `c` has a titlecase form distinct from its uppercase one, such as
+/// the `ǅ` of `Ǆ` and `ǆ`.
+pub fn has_title_form(c: char) -> bool {
+    title_form(c).is_some() || title_letter(c).is_some()
+}
+
+/// The titlecase letter of a Greek letter with a subscript iota, which are
+/// mapped one to one.
+fn title_letter(c: char) -> Option<char> {
+    match c {
+        '\u{1f80}'..='\u{1f87}' | '\u{1f90}'..='\u{1f97}' | '\u{1fa0}'..='\u{1fa7}' => {
+            char::from_u32(c as u32 + 8)
+        }
+        '\u{1f88}'..='\u{1f8f}' | '\u{1f98}'..='\u{1f9f}' | '\u{1fa8}'..='\u{1faf}' => Some(c),
+        '\u{1fb3}' | '\u{1fbc}' => Some('\u{1fbc}'),
+        '\u{1fc3}' | '\u{1fcc}' => Some('\u{1fcc}'),
+        '\u{1ff3}' | '\u{1ffc}' => Some('\u{1ffc}'),
+        _ => None,
+    }
+}
+
+/// Map `c` to titlecase, the form given to the first letter of a word,
+/// applying the conditional mappings of SpecialCasing for its context and
+/// locale.
+pub fn titlecase(c: char, context: &Context, locale: Locale) -> Box<dyn Iterator<Item = char>> {
+    if let Some(title) = title_letter(c) {
+        Box::new(once(title))
+    } else if let Some(title) = title_form(c) {
+        Box::new(title.chars())
+    } else {
+        uppercase(c, context, locale)
+    }
+}
+
+/// The single-codepoint alternative to a lowercase mapping which would change
+/// the length of the text.
+fn lower_alternative(c: char) -> Option<char> {
+    match c {
+        '\u{130}' => Some('i'),
+        _ => None,
+    }
+}
+
+/// The single-codepoint alternative to an uppercase or titlecase mapping which
+/// would change the length of the text.
+fn upper_alternative(c: char) -> Option<char> {
+    match c {
+        'ß' => Some('ẞ'),
+        _ => title_letter(c),
+    }
+}
+
+/// The only character of `chars`, if there is exactly one.
+fn single(mut chars: impl Iterator<Item = char>) -> Option<char> {
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+/// Apply `map` to the first character of the grapheme cluster `g`.
+///
+/// The combining marks which follow it are kept, except for the combining
+/// dot above, which some tailorings remove. When the length is preserved,
+/// a mapping to anything but a single character is replaced with the
+/// `alternative`, then the untailored mapping, and failing both the
+/// character is kept.
+fn map_grapheme(
+    g: &str,
+    context: &Context,
+    rules: Rules,
+    map: impl Fn(char, &Context, Locale) -> Box<dyn Iterator<Item = char>>,
+    alternative: impl Fn(char) -> Option<char>,
+) -> String {
+    let mut mapped = String::with_capacity(g.len());
+    for (i, c) in g.char_indices() {
+        if i > 0 && c != '\u{307}' {
+            mapped.push(c);
+            continue;
+        }
+
+        let context = context.offset(i);
+        if rules.preserve_length {
+            mapped.push(
+                single(map(c, &context, rules.locale))
+                    .or_else(|| alternative(c))
+                    .or_else(|| single(map(c, &context, Locale::Root)))
+                    .unwrap_or(c),
+            );
+        } else {
+            mapped.extend(map(c, &context, rules.locale));
+        }
+    }
+
+    mapped
+}
+
+/// Map the grapheme cluster `g` to lowercase, `context` being that of its
+/// first character.
+pub fn lowercase_grapheme(g: &str, context: &Context, rules: Rules) -> String {
+    map_grapheme(g, context, rules, lowercase, lower_alternative)
+}
+
+/// Map the grapheme cluster `g` to uppercase, `context` being that of its
+/// first character.
+pub fn uppercase_grapheme(g: &str, context: &Context, rules: Rules) -> String {
+    map_grapheme(g, context, rules, uppercase, upper_alternative)
+}
+
+/// Map the grapheme cluster `g` to titlecase, `context` being that of its
+/// first character.
+pub fn titlecase_grapheme(g: &str, context: &Context, rules: Rules) -> String {
+    map_grapheme(g, context, rules, titlecase, upper_alternative)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lower(s: &str) -> String {
+        lower_in(s, Locale::Root)
+    }
+
+    fn lower_in(s: &str, locale: Locale) -> String {
+        s.char_indices()
+            .flat_map(|(i, c)| lowercase(c, &Context::new(s, i), locale))
+            .collect()
+    }
+
+    fn upper_in(s: &str, locale: Locale) -> String {
+        s.char_indices()
+            .flat_map(|(i, c)| uppercase(c, &Context::new(s, i), locale))
+            .collect()
+    }
+
+    #[test]
+    fn lowercase_final_sigma() {
+        assert_eq!(lower("ΟΔΟΣ"), "οδος");
+        assert_eq!(lower("ΟΔΟΣ ΟΔΟΣ."), "οδος οδος.");
+    }
+
+    #[test]
+    fn lowercase_medial_sigma() {
+        assert_eq!(lower("ΣΟΦΙΑ"), "σοφια");
+        assert_eq!(lower("Σ"), "σ");
+    }
+
+    #[test]
+    fn lowercase_final_sigma_ignores_case_ignorable() {
+        assert_eq!(lower("ΟΣ'"), "ος'");
+        assert_eq!(lower("Ο'Σ"), "ο'ς");
+        assert_eq!(lower("ΟΣ'Ο"), "οσ'ο");
+    }
+
+    #[test]
+    fn context_more_above() {
+        assert!(Context::new("I\u{300}", 0).more_above());
+        assert!(Context::new("I\u{323}\u{300}", 0).more_above());
+        assert!(!Context::new("IA\u{300}", 0).more_above());
+    }
+
+    #[test]
+    fn context_before_dot() {
+        assert!(Context::new("I\u{307}", 0).before_dot());
+        assert!(!Context::new("I\u{300}\u{307}", 0).before_dot());
+    }
+
+    #[test]
+    fn context_after_soft_dotted() {
+        assert!(Context::new("i\u{307}", 1).after_soft_dotted());
+        assert!(!Context::new("a\u{307}", 1).after_soft_dotted());
+    }
+
+    #[test]
+    fn context_after_i() {
+        assert!(Context::new("I\u{307}", 1).after_i());
+        assert!(!Context::new("i\u{307}", 1).after_i());
+    }
+
+    #[test]
+    fn turkish_casing() {
+        assert_eq!(upper_in("istanbul", Locale::Turkish), "İSTANBUL");
+        assert_eq!(lower_in("IĞDIR İZMİR", Locale::Turkish), "ığdır izmir");
+        assert_eq!(lower_in("I\u{307}", Locale::Azeri), "i");
+    }
+
+    #[test]
+    fn lithuanian_casing() {
+        assert_eq!(lower_in("I\u{300}", Locale::Lithuanian), "i\u{307}\u{300}");
+        assert_eq!(lower_in("Ì", Locale::Lithuanian), "i\u{307}\u{300}");
+        assert_eq!(upper_in("i\u{307}\u{300}", Locale::Lithuanian), "I\u{300}");
+    }
+
+    #[test]
+    fn root_casing_is_untailored() {
+        assert_eq!(upper_in("istanbul", Locale::Root), "ISTANBUL");
+        assert_eq!(lower_in("I", Locale::Root), "i");
+    }
+
+    #[test]
+    fn grapheme_casing() {
+        let context = Context::new("I\u{307}", 0);
+        let rules = Rules::from(Locale::Turkish);
+        assert_eq!(lowercase_grapheme("I\u{307}", &context, rules), "i");
+
+        let context = Context::new("α\u{345}", 0);
+        let rules = Rules::default();
+        assert_eq!(uppercase_grapheme("α\u{345}", &context, rules), "Α\u{345}");
+    }
+
+    #[test]
+    fn grapheme_casing_preserving_length() {
+        let map = |f: fn(&str, &Context, Rules) -> String, g: &str, locale: Locale| {
+            let rules = Rules {
+                locale,
+                preserve_length: true,
+            };
+            f(g, &Context::new(g, 0), rules)
+        };
+
+        assert_eq!(map(uppercase_grapheme, "ß", Locale::Root), "ẞ");
+        assert_eq!(map(titlecase_grapheme, "ß", Locale::Root), "ẞ");
+        assert_eq!(map(uppercase_grapheme, "ᾳ", Locale::Root), "ᾼ");
+        assert_eq!(map(uppercase_grapheme, "ŉ", Locale::Root), "ŉ");
+        assert_eq!(map(uppercase_grapheme, "ﬁ", Locale::Root), "ﬁ");
+        assert_eq!(map(lowercase_grapheme, "İ", Locale::Root), "i");
+        assert_eq!(map(lowercase_grapheme, "Ì", Locale::Lithuanian), "ì");
+        assert_eq!(
+            map(lowercase_grapheme, "I\u{307}", Locale::Turkish),
+            "i\u{307}"
+        );
+        assert_eq!(
+            map(uppercase_grapheme, "i\u{307}", Locale::Lithuanian),
+            "I\u{307}"
+        );
+    }
+
+    #[test]
+    fn locale_from_str() {
+        assert!(matches!("tr_TR.UTF-8".parse(), Ok(Locale::Turkish)));
+        assert!(matches!("az-Latn".parse(), Ok(Locale::Azeri)));
+        assert!(matches!("lt".parse(), Ok(Locale::Lithuanian)));
+        assert!(matches!("en_GB.UTF-8".parse(), Ok(Locale::Root)));
+        assert!(matches!("C".parse(), Ok(Locale::Root)));
+        assert!(matches!("root".parse(), Ok(Locale::Root)));
+        assert!("garbage".parse::<Locale>().is_err());
+        assert!("".parse::<Locale>().is_err());
+    }
+
+    #[test]
+    fn titlecase_digraphs() {
+        let title = |c: char| -> String { titlecase(c, &Context::none(), Locale::Root).collect() };
+
+        assert_eq!(title('ǆ'), "ǅ");
+        assert_eq!(title('Ǆ'), "ǅ");
+        assert_eq!(title('ǅ'), "ǅ");
+        assert_eq!(title('ǳ'), "ǲ");
+        assert_eq!(title('ß'), "Ss");
+        assert_eq!(title('ﬁ'), "Fi");
+        assert_eq!(title('ᾳ'), "ᾼ");
+        assert_eq!(title('a'), "A");
+    }
+
+    #[test]
+    fn title_forms() {
+        assert!(has_title_form('ǅ'));
+        assert!(has_title_form('ǉ'));
+        assert!(!has_title_form('a'));
+        assert!(!has_title_form('Σ'));
+    }
+
+    #[test]
+    fn cased_titlecase() {
+        assert!(is_titlecase('ǅ'));
+        assert!(is_cased('ǅ'));
+        assert!(!is_cased('1'));
+    }
+}
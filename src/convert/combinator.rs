@@ -0,0 +1,570 @@
+use {
+    crate::convert::{casing::Context, convert_graphemes, random::Dice, Convertor, Settings},
+    anyhow::{Error, Result},
+    rand::RngCore,
+    std::{
+        fmt::{self, Debug, Formatter},
+        iter::{once, Iterator},
+    },
+};
+
+/// Split `s` into runs of whitespace and runs of everything else, the latter
+/// being the words.
+fn runs(s: &str) -> impl Iterator<Item = (bool, &str)> {
+    let mut rest = s;
+    std::iter::from_fn(move || {
+        let first = rest.chars().next()?;
+        let word = !first.is_whitespace();
+        let end = rest
+            .find(|c: char| c.is_whitespace() == word)
+            .unwrap_or(rest.len());
+        let (run, tail) = rest.split_at(end);
+        rest = tail;
+        Some((word, run))
+    })
+}
+
+/// A convertor which runs text through `first`, then its output through
+/// `second`.
+pub struct Chain {
+    first: Box<dyn Convertor>,
+    second: Box<dyn Convertor>,
+}
+
+impl Chain {
+    /// Create a chain of `first` then `second`.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(first: Box<dyn Convertor>, second: Box<dyn Convertor>) -> Box<dyn Convertor> {
+        Box::new(Chain { first, second })
+    }
+}
+
+impl Debug for Chain {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chain").finish_non_exhaustive()
+    }
+}
+
+impl Convertor for Chain {
+    fn convert(&mut self, s: String) -> String {
+        let s = self.first.convert(s);
+        self.second.convert(s)
+    }
+
+    fn reset(&mut self, record: &str) {
+        self.first.reset(record);
+        self.second.reset(record);
+    }
+
+    fn configure(&mut self, settings: &Settings) {
+        self.first.configure(settings);
+        self.second.configure(settings);
+    }
+
+    fn convert_char(&mut self, c: char) -> Box<dyn Iterator<Item = char>> {
+        let second = &mut self.second;
+        let chars: Vec<char> = self
+            .first
+            .convert_char(c)
+            .flat_map(|c| second.convert_char(c))
+            .collect();
+        Box::new(chars.into_iter())
+    }
+}
+
+/// Routes each word to one of several convertors, chosen when the word starts.
+///
+/// Whitespace goes to the convertor chosen for the word before it, so that
+/// convertors which track word boundaries still see them.
+struct Words {
+    convertors: Vec<Box<dyn Convertor>>,
+    current: usize,
+    in_word: bool,
+}
+
+impl Words {
+    fn new(convertors: Vec<Box<dyn Convertor>>) -> Self {
+        Words {
+            convertors,
+            current: 0,
+            in_word: false,
+        }
+    }
+
+    fn convert(&mut self, s: String, mut choose: impl FnMut() -> usize) -> String {
+        let mut converted = String::with_capacity(s.len());
+        for (word, run) in runs(&s) {
+            if word && !self.in_word {
+                self.current = choose();
+            }
+            self.in_word = word;
+            converted.push_str(&self.convertors[self.current].convert(String::from(run)));
+        }
+
+        converted
+    }
+
+    fn convert_char(
+        &mut self,
+        c: char,
+        choose: impl FnOnce() -> usize,
+    ) -> Box<dyn Iterator<Item = char>> {
+        let word = !c.is_whitespace();
+        if word && !self.in_word {
+            self.current = choose();
+        }
+        self.in_word = word;
+        self.convertors[self.current].convert_char(c)
+    }
+
+    fn reset(&mut self, record: &str) {
+        self.in_word = false;
+        for convertor in self.convertors.iter_mut() {
+            convertor.reset(record);
+        }
+    }
+
+    fn configure(&mut self, settings: &Settings) {
+        for convertor in self.convertors.iter_mut() {
+            convertor.configure(settings);
+        }
+    }
+}
+
+/// A convertor which picks one of several convertors at random for each word,
+/// in proportion to their weights.
+pub struct Weighted {
+    words: Words,
+    weights: Vec<u32>,
+    dice: Dice,
+}
+
+impl Weighted {
+    fn with_dice(
+        dice: Dice,
+        choices: Vec<(u32, Box<dyn Convertor>)>,
+    ) -> Result<Box<dyn Convertor>> {
+        let total = choices
+            .iter()
+            .try_fold(0u32, |total, (weight, _)| total.checked_add(*weight))
+            .ok_or_else(|| Error::msg(format!("the weights add up to more than {}", u32::MAX)))?;
+        if total == 0 {
+            return Err(Error::msg("at least one weight must be greater than zero"));
+        }
+
+        let (weights, convertors) = choices.into_iter().unzip();
+        Ok(Box::new(Weighted {
+            words: Words::new(convertors),
+            weights,
+            dice,
+        }))
+    }
+
+    /// Create a weighted choice between convertors, backed by the thread local
+    /// random number generator.
+    ///
+    /// Fails if no weight is greater than zero, or if the weights add up to
+    /// more than `u32::MAX`.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(choices: Vec<(u32, Box<dyn Convertor>)>) -> Result<Box<dyn Convertor>> {
+        Weighted::with_rng(Box::new(rand::thread_rng()), choices)
+    }
+
+    /// Create a weighted choice between convertors, backed by the given random
+    /// number generator.
+    pub fn with_rng(
+        rng: Box<dyn RngCore>,
+        choices: Vec<(u32, Box<dyn Convertor>)>,
+    ) -> Result<Box<dyn Convertor>> {
+        Weighted::with_dice(Dice::new(rng), choices)
+    }
+
+    /// Create a reproducible weighted choice between convertors, backed by a
+    /// ChaCha8 generator seeded from `seed`.
+    pub fn seeded(
+        seed: u64,
+        choices: Vec<(u32, Box<dyn Convertor>)>,
+    ) -> Result<Box<dyn Convertor>> {
+        Weighted::with_dice(Dice::seeded(seed), choices)
+    }
+
+    /// Create a weighted choice between convertors which reseeds itself from a
+    /// hash of each record's content, combined with `seed`.
+    pub fn stable(
+        seed: u64,
+        choices: Vec<(u32, Box<dyn Convertor>)>,
+    ) -> Result<Box<dyn Convertor>> {
+        Weighted::with_dice(Dice::stable(seed), choices)
+    }
+
+    fn choose(weights: &[u32], dice: &mut Dice) -> usize {
+        let mut roll = dice.below(weights.iter().sum());
+        weights
+            .iter()
+            .position(|weight| {
+                if roll < *weight {
+                    true
+                } else {
+                    roll -= weight;
+                    false
+                }
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Manual debug implementation required, to skip the Rng field
+impl Debug for Weighted {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Weighted")
+            .field("weights", &self.weights)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Convertor for Weighted {
+    fn convert(&mut self, s: String) -> String {
+        let (weights, dice) = (&self.weights, &mut self.dice);
+        self.words.convert(s, || Weighted::choose(weights, dice))
+    }
+
+    fn reset(&mut self, record: &str) {
+        self.dice.reset(record);
+        self.words.reset(record);
+    }
+
+    fn configure(&mut self, settings: &Settings) {
+        self.words.configure(settings);
+    }
+
+    fn convert_char(&mut self, c: char) -> Box<dyn Iterator<Item = char>> {
+        let (weights, dice) = (&self.weights, &mut self.dice);
+        self.words
+            .convert_char(c, || Weighted::choose(weights, dice))
+    }
+}
+
+/// A convertor which switches to the next of several convertors every `every`
+/// words, going back to the first after the last.
+pub struct Alternate {
+    words: Words,
+    every: usize,
+    count: usize,
+}
+
+impl Alternate {
+    /// Create an alternation between convertors, switching every `every`
+    /// words.
+    ///
+    /// Fails if `every` is zero or there are no convertors.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(every: usize, convertors: Vec<Box<dyn Convertor>>) -> Result<Box<dyn Convertor>> {
+        if every == 0 {
+            return Err(Error::msg("must switch after at least one word"));
+        } else if convertors.is_empty() {
+            return Err(Error::msg("must alternate between convertors"));
+        }
+
+        Ok(Box::new(Alternate {
+            words: Words::new(convertors),
+            every,
+            count: 0,
+        }))
+    }
+
+    fn choose(every: usize, count: &mut usize, len: usize) -> usize {
+        let index = (*count / every) % len;
+        *count += 1;
+        index
+    }
+}
+
+impl Debug for Alternate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Alternate")
+            .field("every", &self.every)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Convertor for Alternate {
+    fn convert(&mut self, s: String) -> String {
+        let (every, count, len) = (self.every, &mut self.count, self.words.convertors.len());
+        self.words
+            .convert(s, || Alternate::choose(every, count, len))
+    }
+
+    fn reset(&mut self, record: &str) {
+        self.words.reset(record);
+    }
+
+    fn configure(&mut self, settings: &Settings) {
+        self.words.configure(settings);
+    }
+
+    fn convert_char(&mut self, c: char) -> Box<dyn Iterator<Item = char>> {
+        let (every, count, len) = (self.every, &mut self.count, self.words.convertors.len());
+        self.words
+            .convert_char(c, || Alternate::choose(every, count, len))
+    }
+}
+
+enum Predicate {
+    Char(Box<dyn Fn(char) -> bool>),
+    Word(Box<dyn Fn(&str) -> bool>),
+}
+
+/// A convertor which only applies another convertor to the characters or
+/// words matching a predicate, leaving everything else untouched.
+pub struct Conditional {
+    predicate: Predicate,
+    convertor: Box<dyn Convertor>,
+}
+
+impl Conditional {
+    /// Apply `convertor` to the characters for which `predicate` holds.
+    pub fn char(
+        predicate: impl Fn(char) -> bool + 'static,
+        convertor: Box<dyn Convertor>,
+    ) -> Box<dyn Convertor> {
+        Box::new(Conditional {
+            predicate: Predicate::Char(Box::new(predicate)),
+            convertor,
+        })
+    }
+
+    /// Apply `convertor` to the words for which `predicate` holds.
+    ///
+    /// Words are only seen whole by [`Convertor::convert`]. When driven one
+    /// character at a time, the predicate is given each character on its own.
+    pub fn word(
+        predicate: impl Fn(&str) -> bool + 'static,
+        convertor: Box<dyn Convertor>,
+    ) -> Box<dyn Convertor> {
+        Box::new(Conditional {
+            predicate: Predicate::Word(Box::new(predicate)),
+            convertor,
+        })
+    }
+
+    fn matches(&self, c: char) -> bool {
+        match &self.predicate {
+            Predicate::Char(predicate) => predicate(c),
+            Predicate::Word(predicate) => {
+                !c.is_whitespace() && predicate(c.encode_utf8(&mut [0; 4]))
+            }
+        }
+    }
+}
+
+impl Debug for Conditional {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Conditional").finish_non_exhaustive()
+    }
+}
+
+impl Convertor for Conditional {
+    fn convert(&mut self, s: String) -> String {
+        match &self.predicate {
+            Predicate::Char(_) => convert_graphemes(self, &s),
+            Predicate::Word(predicate) => {
+                let mut converted = String::with_capacity(s.len());
+                for (word, run) in runs(&s) {
+                    if word && predicate(run) {
+                        converted.push_str(&self.convertor.convert(String::from(run)));
+                    } else {
+                        converted.push_str(run);
+                    }
+                }
+
+                converted
+            }
+        }
+    }
+
+    fn reset(&mut self, record: &str) {
+        self.convertor.reset(record);
+    }
+
+    fn configure(&mut self, settings: &Settings) {
+        self.convertor.configure(settings);
+    }
+
+    fn convert_grapheme(&mut self, g: &str, context: &Context) -> String {
+        match g.chars().next() {
+            Some(c) if self.matches(c) => self.convertor.convert_grapheme(g, context),
+            _ => String::from(g),
+        }
+    }
+
+    fn convert_char(&mut self, c: char) -> Box<dyn Iterator<Item = char>> {
+        self.convert_char_in(c, &Context::none())
+    }
+
+    fn convert_char_in(&mut self, c: char, context: &Context) -> Box<dyn Iterator<Item = char>> {
+        if self.matches(c) {
+            self.convertor.convert_char_in(c, context)
+        } else {
+            Box::new(once(c))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::convert::{leet::LeetConvertor, leet::Level, simple::SimpleConvertor},
+        rand::rngs::mock::StepRng,
+    };
+
+    fn leet() -> Box<dyn Convertor> {
+        LeetConvertor::with_rng(Box::new(StepRng::new(0, 0)), Level::Light, 100)
+    }
+
+    #[test]
+    fn runs_split_words_and_whitespace() {
+        let runs: Vec<(bool, &str)> = runs(" one  two ").collect();
+
+        assert_eq!(
+            runs,
+            vec![
+                (false, " "),
+                (true, "one"),
+                (false, "  "),
+                (true, "two"),
+                (false, " ")
+            ]
+        );
+    }
+
+    #[test]
+    fn chain_convert() {
+        let mut c = SimpleConvertor::uppercase().chain(leet());
+
+        assert_eq!(c.convert(String::from("leet speak")), "L337 $P34K");
+    }
+
+    #[test]
+    fn chain_convert_char() {
+        let mut c = Chain::new(leet(), SimpleConvertor::uppercase());
+
+        assert_eq!(c.convert_char('s').collect::<String>(), "$");
+        assert_eq!(c.convert_char('p').collect::<String>(), "P");
+    }
+
+    #[test]
+    fn weighted_convert() {
+        let mut c = Weighted::with_rng(
+            Box::new(StepRng::new(0, 1)),
+            vec![
+                (1, SimpleConvertor::uppercase()),
+                (1, SimpleConvertor::lowercase()),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            c.convert(String::from("One Two Three Four")),
+            "ONE two THREE four"
+        );
+    }
+
+    #[test]
+    fn weighted_convert_skips_zero_weight() {
+        let mut c = Weighted::seeded(
+            42,
+            vec![
+                (0, SimpleConvertor::uppercase()),
+                (1, SimpleConvertor::lowercase()),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(c.convert(String::from("One Two Three")), "one two three");
+    }
+
+    #[test]
+    fn weighted_rejects_bad_weights() {
+        let error = Weighted::seeded(42, vec![(0, SimpleConvertor::uppercase())])
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "at least one weight must be greater than zero"
+        );
+
+        assert!(Weighted::seeded(
+            42,
+            vec![
+                (u32::MAX, SimpleConvertor::uppercase()),
+                (1, SimpleConvertor::lowercase()),
+            ],
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn weighted_convert_with_large_weights() {
+        let mut c = Weighted::seeded(
+            42,
+            vec![
+                (u32::MAX - 1, SimpleConvertor::uppercase()),
+                (1, SimpleConvertor::lowercase()),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(c.convert(String::from("One Two")), "ONE TWO");
+    }
+
+    #[test]
+    fn alternate_convert() {
+        let mut c = SimpleConvertor::uppercase()
+            .alternate(SimpleConvertor::lowercase(), 2)
+            .unwrap();
+
+        assert_eq!(
+            c.convert(String::from("One Two Three Four Five")),
+            "ONE TWO three four FIVE"
+        );
+    }
+
+    #[test]
+    fn alternate_convert_char() {
+        let mut c = Alternate::new(
+            1,
+            vec![SimpleConvertor::uppercase(), SimpleConvertor::lowercase()],
+        )
+        .unwrap();
+
+        let converted: String = "One Two"
+            .chars()
+            .flat_map(|ch| c.convert_char(ch))
+            .collect();
+        assert_eq!(converted, "ONE two");
+    }
+
+    #[test]
+    fn alternate_rejects_zero_words() {
+        assert!(Alternate::new(0, vec![SimpleConvertor::uppercase()]).is_err());
+        assert!(Alternate::new(1, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn conditional_char_convert() {
+        let mut c = SimpleConvertor::uppercase().when_char(|c| "aeiou".contains(c));
+
+        assert_eq!(c.convert(String::from("simple string")), "sImplE strIng");
+    }
+
+    #[test]
+    fn conditional_word_convert() {
+        let mut c = SimpleConvertor::uppercase().when_word(|w| w.len() > 3);
+
+        assert_eq!(
+            c.convert(String::from("a simple string")),
+            "a SIMPLE STRING"
+        );
+    }
+}
@@ -0,0 +1,147 @@
+//! Emphasis for the scripts which have no case, standing in for a flip to
+//! uppercase.
+
+use {
+    crate::convert::casing::Context,
+    std::iter::once,
+    unicode_script::{Script, UnicodeScript},
+};
+
+/// The Arabic tatweel, which elongates the joint between two letters.
+const TATWEEL: char = '\u{640}';
+
+/// The Tibetan emphasis mark, placed below a letter.
+const TIBETAN_EMPHASIS: char = '\u{f35}';
+
+/// The scripts which are emphasised differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Family {
+    Cjk,
+    Arabic,
+    Hebrew,
+    Indic,
+}
+
+impl Family {
+    fn of(c: char) -> Option<Self> {
+        match c.script() {
+            Script::Han
+            | Script::Hiragana
+            | Script::Katakana
+            | Script::Hangul
+            | Script::Bopomofo => Some(Family::Cjk),
+            Script::Arabic | Script::Syriac | Script::Thaana => Some(Family::Arabic),
+            Script::Hebrew => Some(Family::Hebrew),
+            Script::Devanagari | Script::Bengali | Script::Gurmukhi | Script::Gujarati => {
+                Some(Family::Indic)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Whether the Arabic letter `c` joins to the letter after it.
+fn joins_next(c: char) -> bool {
+    c.is_alphabetic()
+        && !matches!(
+            c,
+            '\u{621}'..='\u{625}' | '\u{627}' | '\u{629}' | '\u{62f}'..='\u{632}' | '\u{648}'
+                | '\u{671}'..='\u{673}' | '\u{688}'..='\u{699}' | '\u{6c0}'..='\u{6cb}'
+        )
+}
+
+/// The fullwidth form of the printable ASCII character `c`.
+fn fullwidth(c: char) -> Option<char> {
+    match c {
+        '!'..='~' => char::from_u32(c as u32 + 0xfee0),
+        _ => None,
+    }
+}
+
+/// The emphasised form of the grapheme cluster `g`, whose first character has
+/// the given `context`, if its script has one.
+///
+/// - Arabic letters are elongated with a tatweel before the next letter.
+/// - Tibetan letters take the Tibetan emphasis mark.
+/// - Sentence punctuation after a CJK letter becomes a fullwidth `！`, and
+///   other ASCII characters become fullwidth.
+/// - Sentence punctuation after Arabic, Hebrew or Indic letters is doubled,
+///   a danda becoming a double danda.
+pub fn emphasise(g: &str, context: &Context) -> Option<String> {
+    let mut chars = g.chars();
+    let c = chars.next()?;
+    let marks = chars.as_str();
+
+    if c.is_alphabetic() {
+        let next = context
+            .after()
+            .get(marks.len()..)
+            .and_then(|after| after.chars().next());
+
+        return match c.script() {
+            Script::Arabic
+                if joins_next(c) && next.is_some_and(|n| n.script() == Script::Arabic) =>
+            {
+                Some(g.chars().chain(once(TATWEEL)).collect())
+            }
+            Script::Tibetan => Some(g.chars().chain(once(TIBETAN_EMPHASIS)).collect()),
+            _ => None,
+        };
+    }
+
+    let family = context
+        .before()
+        .chars()
+        .rev()
+        .find(|c| c.is_alphabetic())
+        .and_then(Family::of)?;
+
+    let emphasised = match (family, c) {
+        (Family::Cjk, '.' | '。' | '!') => String::from("！"),
+        (Family::Cjk, '！') => String::from("！！"),
+        (Family::Cjk, '?' | '？') => String::from("？！"),
+        (Family::Cjk, _) => fullwidth(c)?.to_string(),
+        (Family::Indic, '।') => String::from("॥"),
+        (_, '!' | '?' | '؟') => c.to_string().repeat(2),
+        _ => return None,
+    };
+
+    Some(emphasised + marks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emphasise_all(s: &str) -> String {
+        use unicode_segmentation::UnicodeSegmentation;
+
+        s.grapheme_indices(true)
+            .map(|(i, g)| emphasise(g, &Context::new(s, i)).unwrap_or_else(|| String::from(g)))
+            .collect()
+    }
+
+    #[test]
+    fn emphasise_cjk() {
+        assert_eq!(emphasise_all("本当に。"), "本当に！");
+        assert_eq!(emphasise_all("何？"), "何？！");
+        assert_eq!(emphasise_all("第1位!"), "第１位！");
+    }
+
+    #[test]
+    fn emphasise_arabic() {
+        assert_eq!(emphasise_all("سلام"), "سـلـام");
+        assert_eq!(emphasise_all("لماذا؟"), "لـمـاذا؟؟");
+    }
+
+    #[test]
+    fn emphasise_hebrew_and_indic() {
+        assert_eq!(emphasise_all("שלום!"), "שלום!!");
+        assert_eq!(emphasise_all("नमस्ते।"), "नमस्ते॥");
+    }
+
+    #[test]
+    fn emphasise_leaves_latin() {
+        assert_eq!(emphasise_all("hello! 1?"), "hello! 1?");
+    }
+}
@@ -0,0 +1,237 @@
+use {
+    crate::convert::{random::Dice, Convertor, Settings},
+    rand::RngCore,
+    std::{
+        fmt::{self, Debug, Display, Formatter},
+        iter::{once, Iterator},
+        str::FromStr,
+    },
+};
+
+/// How far a [`LeetConvertor`] goes when substituting letters.
+///
+/// Each level includes the substitutions of the levels below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Level {
+    /// Single digits and symbols for the common letters, `a` to `4`.
+    #[default]
+    Light,
+    /// Single digits and symbols for most letters, `b` to `8`.
+    Medium,
+    /// Multi-character replacements, `h` to `|-|`.
+    Full,
+}
+
+impl Level {
+    /// The names of all the levels.
+    pub const NAMES: &'static [&'static str] = &["1", "2", "3", "light", "medium", "full"];
+
+    fn substitute(self, c: char) -> Option<&'static str> {
+        let c = c.to_lowercase().next().unwrap_or(c);
+
+        let full = match c {
+            'd' => Some("|)"),
+            'h' => Some("|-|"),
+            'k' => Some("|<"),
+            'm' => Some("|\\/|"),
+            'n' => Some("|\\|"),
+            'u' => Some("|_|"),
+            'v' => Some("\\/"),
+            'w' => Some("\\/\\/"),
+            'x' => Some("><"),
+            'y' => Some("`/"),
+            _ => None,
+        };
+        let medium = match c {
+            'b' => Some("8"),
+            'c' => Some("("),
+            'g' => Some("9"),
+            'l' => Some("1"),
+            'z' => Some("2"),
+            _ => None,
+        };
+        let light = match c {
+            'a' => Some("4"),
+            'e' => Some("3"),
+            'i' => Some("1"),
+            'o' => Some("0"),
+            's' => Some("$"),
+            't' => Some("7"),
+            _ => None,
+        };
+
+        match self {
+            Level::Full => full.or(medium).or(light),
+            Level::Medium => medium.or(light),
+            Level::Light => light,
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "1" | "light" => Ok(Level::Light),
+            "2" | "medium" => Ok(Level::Medium),
+            "3" | "full" => Ok(Level::Full),
+            _ => Err(anyhow::Error::msg(format!(
+                "unknown leet level `{}`, expected one of: {}",
+                s,
+                Level::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Level::Light => write!(f, "light"),
+            Level::Medium => write!(f, "medium"),
+            Level::Full => write!(f, "full"),
+        }
+    }
+}
+
+/// A convertor which substitutes letters with look-alike digits and symbols.
+///
+/// Each letter with a substitution at the chosen level is replaced with a
+/// `percent` chance, all other characters are left untouched. When the length
+/// is preserved, only single character substitutions are made.
+pub struct LeetConvertor {
+    dice: Dice,
+    level: Level,
+    percent: u8,
+    preserve_length: bool,
+}
+
+impl LeetConvertor {
+    fn with_dice(dice: Dice, level: Level, percent: u8) -> Box<dyn Convertor> {
+        Box::new(LeetConvertor {
+            dice,
+            level,
+            percent,
+            preserve_length: false,
+        })
+    }
+
+    /// Create a leet convertor backed by the thread local random number
+    /// generator.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(level: Level, percent: u8) -> Box<dyn Convertor> {
+        LeetConvertor::with_rng(Box::new(rand::thread_rng()), level, percent)
+    }
+
+    /// Create a leet convertor backed by the given random number generator.
+    pub fn with_rng(rng: Box<dyn RngCore>, level: Level, percent: u8) -> Box<dyn Convertor> {
+        LeetConvertor::with_dice(Dice::new(rng), level, percent)
+    }
+
+    /// Create a reproducible leet convertor, backed by a ChaCha8 generator
+    /// seeded from `seed`.
+    pub fn seeded(seed: u64, level: Level, percent: u8) -> Box<dyn Convertor> {
+        LeetConvertor::with_dice(Dice::seeded(seed), level, percent)
+    }
+
+    /// Create a leet convertor which reseeds itself from a hash of each
+    /// record's content, combined with `seed`.
+    pub fn stable(seed: u64, level: Level, percent: u8) -> Box<dyn Convertor> {
+        LeetConvertor::with_dice(Dice::stable(seed), level, percent)
+    }
+}
+
+/// Manual debug implementation required, to skip the Rng field
+impl Debug for LeetConvertor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LeetConvertor")
+            .field("level", &self.level)
+            .field("percent", &self.percent)
+            .field("preserve_length", &self.preserve_length)
+            .finish()
+    }
+}
+
+impl Convertor for LeetConvertor {
+    fn reset(&mut self, record: &str) {
+        self.dice.reset(record);
+    }
+
+    fn configure(&mut self, settings: &Settings) {
+        self.preserve_length = settings.preserve_length;
+    }
+
+    fn convert_char(&mut self, c: char) -> Box<dyn Iterator<Item = char>> {
+        let substitute = self
+            .level
+            .substitute(c)
+            .filter(|s| !self.preserve_length || s.chars().count() == 1);
+
+        match substitute {
+            Some(s) if self.dice.roll() < self.percent as u16 => Box::new(s.chars()),
+            _ => Box::new(once(c)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, rand::rngs::mock::StepRng};
+
+    #[test]
+    fn leet_convert_light() {
+        let mut c = LeetConvertor::with_rng(Box::new(StepRng::new(0, 0)), Level::Light, 100);
+
+        assert_eq!(c.convert(String::from("Leet Speak")), "L337 $p34k");
+    }
+
+    #[test]
+    fn leet_convert_medium() {
+        let mut c = LeetConvertor::with_rng(Box::new(StepRng::new(0, 0)), Level::Medium, 100);
+
+        assert_eq!(c.convert(String::from("Leet Speak")), "1337 $p34k");
+    }
+
+    #[test]
+    fn leet_convert_full() {
+        let mut c = LeetConvertor::with_rng(Box::new(StepRng::new(0, 0)), Level::Full, 100);
+
+        assert_eq!(
+            c.convert(String::from("hack the web")),
+            "|-|4(|< 7|-|3 \\/\\/38"
+        );
+    }
+
+    #[test]
+    fn leet_convert_with_percent() {
+        let mut c = LeetConvertor::with_rng(Box::new(StepRng::new(0, 50)), Level::Light, 50);
+
+        assert_eq!(c.convert(String::from("eeee")), "3e3e");
+    }
+
+    #[test]
+    fn leet_convert_full_preserving_length() {
+        let mut c = LeetConvertor::with_rng(Box::new(StepRng::new(0, 0)), Level::Full, 100);
+        c.configure(&Settings {
+            preserve_length: true,
+            ..Settings::default()
+        });
+
+        assert_eq!(c.convert(String::from("hack the web")), "h4(k 7h3 w38");
+    }
+
+    #[test]
+    fn leet_convert_never() {
+        let mut c = LeetConvertor::with_rng(Box::new(StepRng::new(0, 0)), Level::Full, 0);
+
+        assert_eq!(c.convert(String::from("hack the web")), "hack the web");
+    }
+
+    #[test]
+    fn level_from_str() {
+        assert!(matches!("1".parse::<Level>(), Ok(Level::Light)));
+        assert!(matches!("full".parse::<Level>(), Ok(Level::Full)));
+        assert!("4".parse::<Level>().is_err());
+    }
+}
@@ -1,12 +1,320 @@
+//! Convertors which make text angry, one character at a time.
+
+pub mod alternate;
+pub mod casing;
+pub mod combinator;
+pub mod emphasis;
+pub mod leet;
+pub mod normalize;
 pub mod random;
 pub mod simple;
+pub mod target;
+pub mod titlecase;
+
+use {
+    self::{
+        alternate::{AlternatingConvertor, Case, Restart},
+        casing::{Context, Locale, Rules},
+        combinator::{Alternate, Chain, Conditional, Weighted},
+        leet::{LeetConvertor, Level},
+        normalize::{Form, Normalized},
+        random::{Algorithm, RandomConvertor},
+        simple::SimpleConvertor,
+        target::Targets,
+        titlecase::TitlecaseConvertor,
+    },
+    std::iter::Iterator,
+    unicode_segmentation::UnicodeSegmentation,
+};
+
+/// Parse a percentage between 0 and 100.
+pub fn parse_percent(s: &str) -> anyhow::Result<u8> {
+    let i: u8 = s.parse()?;
+
+    if i > 100 {
+        Err(anyhow::Error::msg(format!(
+            "percentage must be between 0 and 100, was {}",
+            i
+        )))
+    } else {
+        Ok(i)
+    }
+}
 
-use std::iter::Iterator;
+/// Convert `s` one extended grapheme cluster at a time.
+pub(crate) fn convert_graphemes<C: Convertor + ?Sized>(convertor: &mut C, s: &str) -> String {
+    s.grapheme_indices(true)
+        .map(|(i, g)| convertor.convert_grapheme(g, &Context::new(s, i)))
+        .collect()
+}
 
+/// Convert the single character `c` as a grapheme cluster of its own, for
+/// convertors which work on whole clusters.
+pub(crate) fn convert_char_as_grapheme<C: Convertor + ?Sized>(
+    convertor: &mut C,
+    c: char,
+    context: &Context,
+) -> Box<dyn Iterator<Item = char>> {
+    let converted = convertor.convert_grapheme(c.encode_utf8(&mut [0; 4]), context);
+    Box::new(converted.chars().collect::<Vec<_>>().into_iter())
+}
+
+/// A source of converted text.
+///
+/// Implementors only need to provide [`Convertor::convert_char`], which may
+/// expand a single character into several (for example `ß` to `SS`).
 pub trait Convertor {
+    /// Convert a whole string, one extended grapheme cluster at a time.
     fn convert(&mut self, s: String) -> String {
-        s.chars().map(|c| self.convert_char(c)).flatten().collect()
+        convert_graphemes(self, &s)
+    }
+
+    /// Convert a single extended grapheme cluster, whose first character has
+    /// the given `context`.
+    ///
+    /// The default converts the first character with
+    /// [`Convertor::convert_char_in`], and keeps any combining marks or joined
+    /// characters which follow it unchanged, so a cluster is never split.
+    fn convert_grapheme(&mut self, g: &str, context: &Context) -> String {
+        let mut chars = g.chars();
+        match chars.next() {
+            Some(base) => self.convert_char_in(base, context).chain(chars).collect(),
+            None => String::new(),
+        }
     }
 
+    /// Convert a single character, looking at the text around it for
+    /// context-sensitive case mappings such as a final sigma.
+    ///
+    /// The default ignores the context and calls [`Convertor::convert_char`].
+    fn convert_char_in(&mut self, c: char, _context: &Context) -> Box<dyn Iterator<Item = char>> {
+        self.convert_char(c)
+    }
+
+    /// Prepare to convert a new record, such as a line, before its characters
+    /// are passed to [`Convertor::convert`].
+    ///
+    /// Convertors which keep state between records can use this to reset it.
+    /// The default does nothing.
+    fn reset(&mut self, _record: &str) {}
+
+    /// Apply the `settings` which can change after a convertor is built,
+    /// such as the locale of its case mappings.
+    ///
+    /// The default does nothing, for convertors which have no such settings.
+    fn configure(&mut self, _settings: &Settings) {}
+
+    /// Convert a single character into zero or more characters.
     fn convert_char(&mut self, c: char) -> Box<dyn Iterator<Item = char>>;
+
+    /// Run this convertor, then `next` over its output.
+    fn chain(self, next: Box<dyn Convertor>) -> Box<dyn Convertor>
+    where
+        Self: Sized + 'static,
+    {
+        Chain::new(Box::new(self), next)
+    }
+
+    /// Pick either this convertor or `other` for each word, in proportion to
+    /// their weights, failing when both weights are zero.
+    fn weighted(
+        self,
+        weight: u32,
+        other: Box<dyn Convertor>,
+        other_weight: u32,
+    ) -> anyhow::Result<Box<dyn Convertor>>
+    where
+        Self: Sized + 'static,
+    {
+        Weighted::new(vec![(weight, Box::new(self)), (other_weight, other)])
+    }
+
+    /// Switch between this convertor and `other` every `every` words, failing
+    /// when `every` is zero.
+    fn alternate(
+        self,
+        other: Box<dyn Convertor>,
+        every: usize,
+    ) -> anyhow::Result<Box<dyn Convertor>>
+    where
+        Self: Sized + 'static,
+    {
+        Alternate::new(every, vec![Box::new(self), other])
+    }
+
+    /// Only apply this convertor to the characters matching `predicate`.
+    fn when_char(self, predicate: impl Fn(char) -> bool + 'static) -> Box<dyn Convertor>
+    where
+        Self: Sized + 'static,
+    {
+        Conditional::char(predicate, Box::new(self))
+    }
+
+    /// Only apply this convertor to the words matching `predicate`.
+    fn when_word(self, predicate: impl Fn(&str) -> bool + 'static) -> Box<dyn Convertor>
+    where
+        Self: Sized + 'static,
+    {
+        Conditional::word(predicate, Box::new(self))
+    }
+}
+
+/// Boxed convertors are convertors too, so they can be combined.
+impl<C: Convertor + ?Sized> Convertor for Box<C> {
+    fn convert(&mut self, s: String) -> String {
+        (**self).convert(s)
+    }
+
+    fn convert_grapheme(&mut self, g: &str, context: &Context) -> String {
+        (**self).convert_grapheme(g, context)
+    }
+
+    fn convert_char_in(&mut self, c: char, context: &Context) -> Box<dyn Iterator<Item = char>> {
+        (**self).convert_char_in(c, context)
+    }
+
+    fn reset(&mut self, record: &str) {
+        (**self).reset(record)
+    }
+
+    fn configure(&mut self, settings: &Settings) {
+        (**self).configure(settings)
+    }
+
+    fn convert_char(&mut self, c: char) -> Box<dyn Iterator<Item = char>> {
+        (**self).convert_char(c)
+    }
+}
+
+/// The conversions offered by the crate, as selected on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conversion {
+    /// Convert all letters to uppercase.
+    Uppercase,
+    /// Convert all letters to lowercase.
+    Lowercase,
+    /// Swap the case of every letter.
+    Reverse,
+    /// Give the first letter of every word its titlecase form and lowercase
+    /// the rest.
+    Titlecase,
+    /// Randomly flip letters with a percentage chance and an optional step
+    /// increase of that chance after each letter that was not flipped.
+    Random(u8, Option<u8>),
+    /// Strictly alternate the case of letters, starting with the given case
+    /// and going back to it at each restart point.
+    Alternate(Case, Restart),
+    /// Substitute letters with digits and symbols up to the given level, each
+    /// with a percentage chance.
+    Leet(Level, u8),
+    /// Make sentence punctuation angry.
+    Punctuate,
+}
+
+/// Where a convertor gets the random numbers for its choices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Randomness {
+    /// The thread local random number generator.
+    #[default]
+    Thread,
+    /// A generator seeded once, so the whole input is reproducible.
+    Seeded(u64),
+    /// A generator reseeded from the seed and a hash of each record.
+    Stable(u64),
+}
+
+impl Randomness {
+    /// The same kind of randomness, with the seed moved on by `offset`.
+    pub fn offset(self, offset: u64) -> Self {
+        match self {
+            Randomness::Thread => Randomness::Thread,
+            Randomness::Seeded(seed) => Randomness::Seeded(seed.wrapping_add(offset)),
+            Randomness::Stable(seed) => Randomness::Stable(seed.wrapping_add(offset)),
+        }
+    }
+}
+
+/// Settings shared by every convertor built from a [`Conversion`].
+///
+/// Settings are not `Copy`, as the [`Targets`] own their character sets and
+/// regexes, so they are passed by reference or cloned.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Settings {
+    /// The source of random choices.
+    pub randomness: Randomness,
+    /// The flip logic of random conversions, by default
+    /// [`Algorithm::LATEST`] with the thread local generator and
+    /// [`Algorithm::V1`] with a seed, so that seeds keep their output.
+    pub algorithm: Option<Algorithm>,
+    /// The language whose case mappings are used.
+    pub locale: Locale,
+    /// The locale was taken from the environment rather than chosen. Random
+    /// conversions with [`Algorithm::V1`], which predates locales, then
+    /// ignore it so that a seed keeps giving the same output.
+    pub locale_from_env: bool,
+    /// Convert every character to exactly one character, so the converted
+    /// text has as many characters as the original.
+    pub preserve_length: bool,
+    /// The normalization form of the text going in and out of the convertor,
+    /// if any.
+    pub normalization: Option<Form>,
+    /// The classes of characters to convert, each with an optional chance of
+    /// its own.
+    pub targets: Targets,
+}
+
+impl Settings {
+    /// The rules followed by the case mappings.
+    pub fn rules(&self) -> Rules {
+        Rules {
+            locale: self.locale,
+            preserve_length: self.preserve_length,
+        }
+    }
+}
+
+impl Conversion {
+    /// Build the convertor which performs this conversion.
+    pub fn convertor(&self) -> Box<dyn Convertor> {
+        self.convertor_with(&Settings::default())
+    }
+
+    /// Build the convertor which performs this conversion with the given
+    /// `settings`.
+    pub fn convertor_with(&self, settings: &Settings) -> Box<dyn Convertor> {
+        let mut convertor = match (*self, settings.randomness) {
+            (Conversion::Uppercase, _) => SimpleConvertor::uppercase(),
+            (Conversion::Lowercase, _) => SimpleConvertor::lowercase(),
+            (Conversion::Reverse, _) => SimpleConvertor::reverse(),
+            (Conversion::Titlecase, _) => TitlecaseConvertor::new(),
+            (Conversion::Random(percent, step), Randomness::Thread) => RandomConvertor::unseeded(
+                settings.algorithm.unwrap_or(Algorithm::LATEST),
+                percent,
+                step,
+            ),
+            (Conversion::Random(percent, step), Randomness::Seeded(seed)) => {
+                RandomConvertor::seeded(seed, settings.algorithm.unwrap_or_default(), percent, step)
+            }
+            (Conversion::Random(percent, step), Randomness::Stable(seed)) => {
+                RandomConvertor::stable(seed, settings.algorithm.unwrap_or_default(), percent, step)
+            }
+            (Conversion::Alternate(start, restart), _) => AlternatingConvertor::new(start, restart),
+            (Conversion::Leet(level, percent), Randomness::Thread) => {
+                LeetConvertor::new(level, percent)
+            }
+            (Conversion::Leet(level, percent), Randomness::Seeded(seed)) => {
+                LeetConvertor::seeded(seed, level, percent)
+            }
+            (Conversion::Leet(level, percent), Randomness::Stable(seed)) => {
+                LeetConvertor::stable(seed, level, percent)
+            }
+            (Conversion::Punctuate, _) => SimpleConvertor::punctuate(),
+        };
+        convertor.configure(settings);
+
+        match settings.normalization {
+            Some(form) => Normalized::new(form, convertor),
+            None => convertor,
+        }
+    }
 }
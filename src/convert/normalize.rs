@@ -0,0 +1,176 @@
+use {
+    crate::convert::{casing::Context, convert_char_as_grapheme, Convertor, Settings},
+    std::{
+        fmt::{self, Debug, Display, Formatter},
+        iter::Iterator,
+        str::FromStr,
+    },
+    unicode_normalization::UnicodeNormalization,
+};
+
+/// A Unicode normalization form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Form {
+    /// Canonical composition.
+    Nfc,
+    /// Canonical decomposition.
+    Nfd,
+    /// Compatibility composition.
+    Nfkc,
+    /// Compatibility decomposition.
+    Nfkd,
+}
+
+impl Form {
+    /// The names of all the forms.
+    pub const NAMES: &'static [&'static str] = &["nfc", "nfd", "nfkc", "nfkd"];
+
+    /// Normalize `s` to this form.
+    pub fn apply(self, s: &str) -> String {
+        match self {
+            Form::Nfc => s.nfc().collect(),
+            Form::Nfd => s.nfd().collect(),
+            Form::Nfkc => s.nfkc().collect(),
+            Form::Nfkd => s.nfkd().collect(),
+        }
+    }
+}
+
+impl FromStr for Form {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "nfc" => Ok(Form::Nfc),
+            "nfd" => Ok(Form::Nfd),
+            "nfkc" => Ok(Form::Nfkc),
+            "nfkd" => Ok(Form::Nfkd),
+            _ => Err(anyhow::Error::msg(format!(
+                "unknown normalization form `{}`, expected one of: {}",
+                s,
+                Form::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+impl Display for Form {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Form::Nfc => write!(f, "nfc"),
+            Form::Nfd => write!(f, "nfd"),
+            Form::Nfkc => write!(f, "nfkc"),
+            Form::Nfkd => write!(f, "nfkd"),
+        }
+    }
+}
+
+/// A convertor which normalizes text to a [`Form`] before passing it to
+/// another convertor, and normalizes what comes out of it again.
+///
+/// Records given to [`Convertor::reset`] are normalized too, so text which
+/// only differs in its encoding converts to exactly the same output, even
+/// when the random choices are seeded from each record.
+pub struct Normalized {
+    form: Form,
+    convertor: Box<dyn Convertor>,
+}
+
+impl Normalized {
+    /// Normalize the input and output of `convertor` to `form`.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(form: Form, convertor: Box<dyn Convertor>) -> Box<dyn Convertor> {
+        Box::new(Normalized { form, convertor })
+    }
+}
+
+impl Debug for Normalized {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Normalized")
+            .field("form", &self.form)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Convertor for Normalized {
+    fn convert(&mut self, s: String) -> String {
+        let converted = self.convertor.convert(self.form.apply(&s));
+        self.form.apply(&converted)
+    }
+
+    fn convert_grapheme(&mut self, g: &str, _context: &Context) -> String {
+        self.convert(String::from(g))
+    }
+
+    fn convert_char_in(&mut self, c: char, context: &Context) -> Box<dyn Iterator<Item = char>> {
+        convert_char_as_grapheme(self, c, context)
+    }
+
+    fn reset(&mut self, record: &str) {
+        self.convertor.reset(&self.form.apply(record));
+    }
+
+    fn configure(&mut self, settings: &Settings) {
+        self.convertor.configure(settings);
+    }
+
+    fn convert_char(&mut self, c: char) -> Box<dyn Iterator<Item = char>> {
+        self.convert_char_in(c, &Context::none())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::convert::{random::RandomConvertor, simple::SimpleConvertor},
+    };
+
+    #[test]
+    fn form_apply() {
+        assert_eq!(Form::Nfc.apply("e\u{301}"), "\u{e9}");
+        assert_eq!(Form::Nfd.apply("\u{e9}"), "e\u{301}");
+        assert_eq!(Form::Nfkc.apply("ﬁ"), "fi");
+        assert_eq!(Form::Nfkd.apply("\u{1e9b}"), "s\u{307}");
+    }
+
+    #[test]
+    fn normalized_convert() {
+        let mut c = Normalized::new(Form::Nfc, SimpleConvertor::uppercase());
+
+        assert_eq!(c.convert(String::from("cafe\u{301}")), "CAF\u{c9}");
+        assert_eq!(c.convert(String::from("caf\u{e9}")), "CAF\u{c9}");
+    }
+
+    #[test]
+    fn normalized_convert_output() {
+        // Uppercasing ǰ gives a J followed by a combining caron
+        let mut c = Normalized::new(Form::Nfd, SimpleConvertor::uppercase());
+
+        assert_eq!(c.convert(String::from("\u{1f0}")), "J\u{30c}");
+    }
+
+    #[test]
+    fn normalized_stable_convert_ignores_encoding() {
+        let mut c = Normalized::new(
+            Form::Nfc,
+            RandomConvertor::stable(0, Default::default(), 50, None),
+        );
+
+        let mut convert = |s: &str| {
+            c.reset(s);
+            c.convert(String::from(s))
+        };
+
+        assert_eq!(
+            convert("cre\u{300}me bru\u{302}le\u{301}e"),
+            convert("cr\u{e8}me br\u{fb}l\u{e9}e")
+        );
+    }
+
+    #[test]
+    fn form_from_str() {
+        assert!(matches!("nfkd".parse::<Form>(), Ok(Form::Nfkd)));
+        assert!("nfx".parse::<Form>().is_err());
+    }
+}
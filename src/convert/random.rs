@@ -1,34 +1,226 @@
 use {
-    crate::convert::Convertor,
-    rand::{Rng, RngCore},
+    crate::convert::{
+        casing::{self, Context, Locale, Rules},
+        convert_char_as_grapheme, emphasis,
+        target::Targets,
+        Convertor, Settings,
+    },
+    rand::{RngCore, SeedableRng},
+    rand_chacha::ChaCha8Rng,
     std::{
-        fmt::{Debug, Formatter, Result},
-        iter::{once, Iterator},
+        fmt::{self, Debug, Display, Formatter},
+        iter::Iterator,
+        str::FromStr,
     },
 };
 
+/// The version of the flip logic used by a [`RandomConvertor`].
+///
+/// A seeded convertor is guaranteed to produce the same output for the same
+/// input, seed and algorithm across releases. Changes to the flip logic are
+/// introduced as a new algorithm, leaving existing ones untouched. The default
+/// stays at [`Algorithm::V1`] for seeded convertors, while convertors whose
+/// output cannot be reproduced anyway use [`Algorithm::LATEST`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    /// Draw a value in `0..100` from the low 16 bits of each 32 bit output
+    /// and flip the letter to uppercase if it is below the current percentage.
+    ///
+    /// Every letter draws, including the letters which are marks within a
+    /// grapheme cluster, and is mapped on its own, ignoring the text around
+    /// it.
+    #[default]
+    V1,
+    /// Draw as [`Algorithm::V1`] does, but once for each grapheme cluster
+    /// starting with a letter, mapping the whole cluster with the
+    /// context-sensitive rules of SpecialCasing. Titlecase letters such as
+    /// `ǅ` are flipped too, and a flipped letter with a distinct titlecase
+    /// form draws again, taking the titlecase form on a value below 50.
+    V2,
+    /// As [`Algorithm::V2`] for letters with a case. Characters without one
+    /// only draw when their script has a form of emphasis, which a flip
+    /// applies in place of uppercase.
+    V3,
+}
+
+impl Algorithm {
+    /// The names of all the available algorithms.
+    pub const NAMES: &'static [&'static str] = &["v1", "v2", "v3"];
+
+    /// The newest algorithm.
+    pub const LATEST: Algorithm = Algorithm::V3;
+}
+
+impl FromStr for Algorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "v1" => Ok(Algorithm::V1),
+            "v2" => Ok(Algorithm::V2),
+            "v3" => Ok(Algorithm::V3),
+            _ => Err(anyhow::Error::msg(format!(
+                "unknown algorithm `{}`, expected one of: {}",
+                s,
+                Algorithm::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+impl Display for Algorithm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Algorithm::V1 => write!(f, "v1"),
+            Algorithm::V2 => write!(f, "v2"),
+            Algorithm::V3 => write!(f, "v3"),
+        }
+    }
+}
+
+/// A convertor which randomly flips letters to uppercase or lowercase.
+///
+/// Each letter is uppercased with a `percent` chance. When a `step` is given,
+/// that chance grows by `step` after every letter that was not flipped, and
+/// resets once a letter is flipped. Characters without a case are emphasised
+/// instead, in the scripts which have a form of emphasis (see
+/// [`emphasis::emphasise`]).
+///
+/// When targets are configured, only the targeted letters are flipped, and
+/// those in a class with a chance of its own are flipped with that chance,
+/// regardless of the step.
 pub struct RandomConvertor {
-    rng: Box<dyn RngCore>,
+    dice: Dice,
+    algorithm: Algorithm,
     percent: u8,
     step: Option<u8>,
     flipped: bool,
     current: u16,
+    rules: Rules,
+    targets: Targets,
+}
+
+/// Hash a record with 64 bit FNV-1a, starting from `seed`.
+///
+/// Unlike the hashers in the standard library, the result is guaranteed to be
+/// the same on every platform and release.
+fn hash(seed: u64, record: &str) -> u64 {
+    record
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325 ^ seed, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+/// Percentage rolls shared by the convertors which make random choices.
+pub(crate) struct Dice {
+    rng: Box<dyn RngCore>,
+    stable: Option<u64>,
+}
+
+impl Dice {
+    pub(crate) fn new(rng: Box<dyn RngCore>) -> Self {
+        Dice { rng, stable: None }
+    }
+
+    pub(crate) fn seeded(seed: u64) -> Self {
+        Dice::new(Box::new(ChaCha8Rng::seed_from_u64(seed)))
+    }
+
+    /// Dice which are reseeded from `seed` and the content of each record.
+    pub(crate) fn stable(seed: u64) -> Self {
+        Dice {
+            stable: Some(seed),
+            ..Dice::seeded(seed)
+        }
+    }
+
+    /// Reseed stable dice for the next record, returning whether they were.
+    pub(crate) fn reset(&mut self, record: &str) -> bool {
+        if let Some(seed) = self.stable {
+            self.rng = Box::new(ChaCha8Rng::seed_from_u64(hash(seed, record)));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Roll a value in `0..100`, from the low 16 bits of a 32 bit output.
+    pub(crate) fn roll(&mut self) -> u16 {
+        (self.rng.next_u32() as u16) % 100
+    }
+
+    /// Roll a value in `0..n`, which must not be zero.
+    pub(crate) fn below(&mut self, n: u32) -> u32 {
+        self.rng.next_u32() % n
+    }
 }
 
 impl RandomConvertor {
-    fn with_rng(rng: Box<dyn RngCore>, percent: u8, step: Option<u8>) -> Box<dyn Convertor> {
+    fn with_dice(
+        dice: Dice,
+        algorithm: Algorithm,
+        percent: u8,
+        step: Option<u8>,
+    ) -> Box<dyn Convertor> {
         Box::new(RandomConvertor {
-            rng,
+            dice,
+            algorithm,
             percent,
             step,
             flipped: false,
             current: percent as u16,
+            rules: Rules::default(),
+            targets: Targets::default(),
         })
     }
 
+    /// Create a random convertor backed by the thread local random number
+    /// generator, using the latest algorithm.
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(percent: u8, step: Option<u8>) -> Box<dyn Convertor> {
-        let rng = Box::new(rand::thread_rng());
-        RandomConvertor::with_rng(rng, percent, step)
+        RandomConvertor::unseeded(Algorithm::LATEST, percent, step)
+    }
+
+    /// Create a random convertor backed by the thread local random number
+    /// generator, using the given `algorithm`.
+    pub fn unseeded(algorithm: Algorithm, percent: u8, step: Option<u8>) -> Box<dyn Convertor> {
+        let dice = Dice::new(Box::new(rand::thread_rng()));
+        RandomConvertor::with_dice(dice, algorithm, percent, step)
+    }
+
+    /// Create a random convertor backed by the given random number generator,
+    /// using the default algorithm.
+    pub fn with_rng(rng: Box<dyn RngCore>, percent: u8, step: Option<u8>) -> Box<dyn Convertor> {
+        RandomConvertor::with_dice(Dice::new(rng), Algorithm::default(), percent, step)
+    }
+
+    /// Create a reproducible random convertor, backed by a ChaCha8 generator
+    /// seeded from `seed`.
+    ///
+    /// The same seed and algorithm always give the same output for the same
+    /// input.
+    pub fn seeded(
+        seed: u64,
+        algorithm: Algorithm,
+        percent: u8,
+        step: Option<u8>,
+    ) -> Box<dyn Convertor> {
+        RandomConvertor::with_dice(Dice::seeded(seed), algorithm, percent, step)
+    }
+
+    /// Create a random convertor which reseeds itself from a hash of each
+    /// record's content, combined with `seed`.
+    ///
+    /// The same record always gives the same output, wherever it appears in
+    /// the input, so unchanged lines stay byte-identical between runs.
+    pub fn stable(
+        seed: u64,
+        algorithm: Algorithm,
+        percent: u8,
+        step: Option<u8>,
+    ) -> Box<dyn Convertor> {
+        RandomConvertor::with_dice(Dice::stable(seed), algorithm, percent, step)
     }
 
     fn current(&mut self) -> u16 {
@@ -43,6 +235,14 @@ impl RandomConvertor {
         current
     }
 
+    /// The chance of flipping `c`, from its target or the current percentage.
+    fn chance(&mut self, c: char) -> u16 {
+        match self.targets.percent(c) {
+            Some(percent) => percent as u16,
+            None => self.current(),
+        }
+    }
+
     fn flip(&mut self, flipped: bool) {
         self.flipped = flipped;
         if flipped {
@@ -50,51 +250,148 @@ impl RandomConvertor {
         }
     }
 
-    fn rng(&mut self) -> u16 {
-        self.rng.gen::<u16>() % 100
+    /// Convert `c` with no context, so that the conditional mappings of
+    /// SpecialCasing, which came after V1, never change its output.
+    fn convert_char_v1(&mut self, c: char) -> String {
+        let mut buffer = [0; 4];
+        let s = c.encode_utf8(&mut buffer);
+
+        if c.is_alphabetic() && self.targets.contains(c) {
+            if self.dice.roll() < self.chance(c) {
+                self.flip(true);
+
+                if c.is_lowercase() {
+                    return casing::uppercase_grapheme(s, &Context::none(), self.rules);
+                }
+            } else {
+                self.flip(false);
+
+                if c.is_uppercase() {
+                    return casing::lowercase_grapheme(s, &Context::none(), self.rules);
+                }
+            }
+        }
+
+        String::from(s)
+    }
+
+    fn convert_grapheme_v1(&mut self, g: &str) -> String {
+        g.chars().map(|c| self.convert_char_v1(c)).collect()
+    }
+
+    fn convert_grapheme_v2(&mut self, g: &str, context: &Context) -> String {
+        match g.chars().next() {
+            Some(c) if c.is_alphabetic() && self.targets.contains(c) => {
+                if self.dice.roll() < self.chance(c) {
+                    self.flip(true);
+
+                    if casing::has_title_form(c) && self.dice.roll() < 50 {
+                        casing::titlecase_grapheme(g, context, self.rules)
+                    } else if c.is_uppercase() {
+                        String::from(g)
+                    } else {
+                        casing::uppercase_grapheme(g, context, self.rules)
+                    }
+                } else {
+                    self.flip(false);
+
+                    if c.is_lowercase() {
+                        String::from(g)
+                    } else {
+                        casing::lowercase_grapheme(g, context, self.rules)
+                    }
+                }
+            }
+            _ => String::from(g),
+        }
+    }
+
+    fn convert_grapheme_v3(&mut self, g: &str, context: &Context) -> String {
+        match g.chars().next() {
+            Some(c) if casing::is_cased(c) => self.convert_grapheme_v2(g, context),
+            Some(c) if self.targets.contains(c) => {
+                let emphasised = emphasis::emphasise(g, context).filter(|emphasised| {
+                    !self.rules.preserve_length || emphasised.chars().count() == g.chars().count()
+                });
+
+                match emphasised {
+                    Some(emphasised) => {
+                        let flipped = self.dice.roll() < self.chance(c);
+                        self.flip(flipped);
+
+                        if flipped {
+                            emphasised
+                        } else {
+                            String::from(g)
+                        }
+                    }
+                    None => String::from(g),
+                }
+            }
+            _ => String::from(g),
+        }
     }
 }
 
 /// Manual debug implementation required, to skip the Rng field
 impl Debug for RandomConvertor {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_struct("RandomConvertor")
+            .field("algorithm", &self.algorithm)
             .field("percent", &self.percent)
             .field("step", &self.step)
+            .field("rules", &self.rules)
+            .field("targets", &self.targets)
             .finish()
     }
 }
 
 impl Convertor for RandomConvertor {
-    fn convert_char(&mut self, c: char) -> Box<dyn Iterator<Item = char>> {
-        if c.is_alphabetic() {
-            if self.rng() < self.current() {
-                self.flip(true);
+    fn reset(&mut self, record: &str) {
+        if self.dice.reset(record) {
+            self.flipped = false;
+            self.current = self.percent as u16;
+        }
+    }
 
-                if c.is_lowercase() {
-                    Box::new(c.to_uppercase())
-                } else {
-                    Box::new(once(c))
-                }
-            } else {
-                self.flip(false);
+    fn configure(&mut self, settings: &Settings) {
+        self.rules = settings.rules();
+        if self.algorithm == Algorithm::V1 && settings.locale_from_env {
+            self.rules.locale = Locale::Root;
+        }
+        self.targets = settings.targets.clone();
+    }
 
-                if c.is_uppercase() {
-                    Box::new(c.to_lowercase())
-                } else {
-                    Box::new(once(c))
-                }
-            }
-        } else {
-            Box::new(once(c))
+    fn convert_grapheme(&mut self, g: &str, context: &Context) -> String {
+        match self.algorithm {
+            Algorithm::V1 => self.convert_grapheme_v1(g),
+            Algorithm::V2 => self.convert_grapheme_v2(g, context),
+            Algorithm::V3 => self.convert_grapheme_v3(g, context),
         }
     }
+
+    fn convert_char_in(&mut self, c: char, context: &Context) -> Box<dyn Iterator<Item = char>> {
+        convert_char_as_grapheme(self, c, context)
+    }
+
+    fn convert_char(&mut self, c: char) -> Box<dyn Iterator<Item = char>> {
+        self.convert_char_in(c, &Context::none())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use {super::*, rand::rngs::mock::StepRng};
 
+    fn with_algorithm(
+        rng: StepRng,
+        algorithm: Algorithm,
+        percent: u8,
+        step: Option<u8>,
+    ) -> Box<dyn Convertor> {
+        RandomConvertor::with_dice(Dice::new(Box::new(rng)), algorithm, percent, step)
+    }
+
     #[test]
     fn random_convert_no_step() {
         let rng = Box::new(StepRng::new(50, 50));
@@ -126,4 +423,244 @@ mod tests {
 
         assert_eq!(c.convert(String::from("simple string")), "SimpLe stRing");
     }
+
+    #[test]
+    fn random_convert_one_draw_per_grapheme() {
+        let mut c = with_algorithm(StepRng::new(50, 50), Algorithm::V2, 50, None);
+
+        assert_eq!(
+            c.convert(String::from("\u{915}\u{93f}ab")),
+            "\u{915}\u{93f}Ab"
+        );
+    }
+
+    #[test]
+    fn random_convert_v1_draws_per_char() {
+        let mut c = with_algorithm(StepRng::new(50, 50), Algorithm::V1, 50, None);
+
+        assert_eq!(
+            c.convert(String::from("\u{915}\u{93f}ab")),
+            "\u{915}\u{93f}aB"
+        );
+    }
+
+    #[test]
+    fn random_convert_v3_one_draw_per_grapheme() {
+        let mut c = with_algorithm(StepRng::new(50, 50), Algorithm::V3, 50, None);
+
+        assert_eq!(c.convert(String::from("e\u{301}ab")), "e\u{301}Ab");
+    }
+
+    #[test]
+    fn random_convert_final_sigma() {
+        let mut c = with_algorithm(StepRng::new(0, 0), Algorithm::V2, 0, None);
+
+        assert_eq!(c.convert(String::from("ΟΔΟΣ")), "οδος");
+    }
+
+    #[test]
+    fn random_convert_in_locale() {
+        let rng = Box::new(StepRng::new(0, 0));
+        let mut c = RandomConvertor::with_rng(rng, 100, None);
+        c.configure(&Settings {
+            locale: Locale::Azeri,
+            ..Settings::default()
+        });
+
+        assert_eq!(c.convert(String::from("bir iki")), "BİR İKİ");
+    }
+
+    #[test]
+    fn random_convert_with_targets() {
+        let rng = Box::new(StepRng::new(50, 0));
+        let mut c = RandomConvertor::with_rng(rng, 0, None);
+        c.configure(&Settings {
+            targets: Targets::new(vec![
+                "vowels=80".parse().unwrap(),
+                "consonants=30".parse().unwrap(),
+            ]),
+            ..Settings::default()
+        });
+
+        assert_eq!(c.convert(String::from("simple string")), "sImplE strIng");
+    }
+
+    #[test]
+    fn random_convert_only_targets() {
+        let rng = Box::new(StepRng::new(0, 0));
+        let mut c = RandomConvertor::with_rng(rng, 100, None);
+        c.configure(&Settings {
+            targets: Targets::new(vec!["chars:aeiou".parse().unwrap()]),
+            ..Settings::default()
+        });
+
+        assert_eq!(c.convert(String::from("simple string")), "sImplE strIng");
+    }
+
+    #[test]
+    fn random_convert_emphasises_caseless_scripts() {
+        let mut c = with_algorithm(StepRng::new(0, 0), Algorithm::V3, 100, None);
+
+        assert_eq!(
+            c.convert(String::from("本当に? 第1位")),
+            "本当に？！ 第１位"
+        );
+        assert_eq!(c.convert(String::from("سلام!")), "سـلـام!!");
+    }
+
+    #[test]
+    fn random_convert_v2_ignores_caseless_scripts() {
+        let mut c = RandomConvertor::seeded(0, Algorithm::V2, 100, None);
+
+        assert_eq!(c.convert(String::from("本当に? سلام!")), "本当に? سلام!");
+    }
+
+    #[test]
+    fn random_convert_emphasis_preserving_length() {
+        let mut c = with_algorithm(StepRng::new(0, 0), Algorithm::V3, 100, None);
+        c.configure(&Settings {
+            preserve_length: true,
+            ..Settings::default()
+        });
+
+        assert_eq!(
+            c.convert(String::from("本当に。何? سلام")),
+            "本当に！何? سلام"
+        );
+    }
+
+    #[test]
+    fn seeded_convert_is_repeatable() {
+        let mut a = RandomConvertor::seeded(42, Algorithm::V1, 50, None);
+        let mut b = RandomConvertor::seeded(42, Algorithm::V1, 50, None);
+
+        assert_eq!(
+            a.convert(String::from("a much longer simple string")),
+            b.convert(String::from("a much longer simple string"))
+        );
+    }
+
+    #[test]
+    fn seeded_convert_v1_is_stable() {
+        let mut c = RandomConvertor::seeded(42, Algorithm::V1, 50, None);
+
+        assert_eq!(c.convert(String::from("simple string")), "simPle sTrINg");
+    }
+
+    #[test]
+    fn seeded_convert_v1_is_stable_across_scripts() {
+        let mut c = RandomConvertor::seeded(7, Algorithm::V1, 50, None);
+
+        assert_eq!(c.convert(String::from("कि ab cd ef")), "कि ab cd eF");
+    }
+
+    #[test]
+    fn seeded_convert_v1_ignores_final_sigma() {
+        let mut c = RandomConvertor::seeded(7, Algorithm::V1, 50, None);
+
+        assert_eq!(
+            c.convert(String::from("ΟΔΟΣ οδος ΣΟΦΙΑΣ")),
+            "ΟΔοσ οδοΣ ΣοφιαΣ"
+        );
+    }
+
+    #[test]
+    fn seeded_convert_v1_ignores_locale_from_env() {
+        let settings = Settings {
+            locale: Locale::Turkish,
+            locale_from_env: true,
+            ..Settings::default()
+        };
+
+        let mut c = RandomConvertor::seeded(7, Algorithm::V1, 50, None);
+        c.configure(&settings);
+        assert_eq!(
+            c.convert(String::from("istanbul iii IIII")),
+            "IStanbuL Iii iiIi"
+        );
+
+        let mut c = RandomConvertor::seeded(7, Algorithm::V1, 50, None);
+        c.configure(&Settings {
+            locale_from_env: false,
+            ..settings
+        });
+        assert_eq!(
+            c.convert(String::from("istanbul iii IIII")),
+            "İStanbuL İii ııIı"
+        );
+    }
+
+    #[test]
+    fn seeded_convert_v1_keeps_titlecase() {
+        let mut c = RandomConvertor::seeded(0, Algorithm::V1, 100, None);
+        assert_eq!(c.convert(String::from("ǅ")), "ǅ");
+
+        let mut c = RandomConvertor::seeded(0, Algorithm::V1, 0, None);
+        assert_eq!(c.convert(String::from("ǅ")), "ǅ");
+    }
+
+    #[test]
+    fn random_convert_titlecase_forms() {
+        // Every draw is 0: flip, then take the titlecase form
+        let mut c = with_algorithm(StepRng::new(0, 0), Algorithm::V2, 100, None);
+        assert_eq!(c.convert(String::from("ǆ Ǆ")), "ǅ ǅ");
+
+        // Every draw is 99: never flip, lowercase
+        let mut c = with_algorithm(StepRng::new(99, 0), Algorithm::V2, 50, None);
+        assert_eq!(c.convert(String::from("ǅ Ǆ")), "ǆ ǆ");
+
+        // Flip, then draw 50: uppercase
+        let mut c = with_algorithm(StepRng::new(0, 50), Algorithm::V2, 100, None);
+        assert_eq!(c.convert(String::from("ǅ")), "Ǆ");
+    }
+
+    #[test]
+    fn seeded_convert_v2_is_stable() {
+        let mut c = RandomConvertor::seeded(42, Algorithm::V2, 50, None);
+
+        assert_eq!(c.convert(String::from("simple string")), "simPle sTrINg");
+    }
+
+    #[test]
+    fn seeded_convert_v3_is_stable() {
+        let mut c = RandomConvertor::seeded(42, Algorithm::V3, 50, None);
+
+        assert_eq!(c.convert(String::from("simple string")), "simPle sTrINg");
+    }
+
+    #[test]
+    fn algorithm_from_str() {
+        assert!(matches!("v1".parse::<Algorithm>(), Ok(Algorithm::V1)));
+        assert!(matches!("v2".parse::<Algorithm>(), Ok(Algorithm::V2)));
+        assert!(matches!("v3".parse::<Algorithm>(), Ok(Algorithm::V3)));
+        assert!("v0".parse::<Algorithm>().is_err());
+    }
+
+    #[test]
+    fn stable_convert_depends_on_content_only() {
+        let mut c = RandomConvertor::stable(0, Algorithm::V1, 50, Some(10));
+
+        let mut convert = |s: &str| {
+            c.reset(s);
+            c.convert(String::from(s))
+        };
+
+        let first = convert("the same line");
+        convert("a different line in between");
+        assert_eq!(convert("the same line"), first);
+    }
+
+    #[test]
+    fn stable_convert_v1_is_stable() {
+        let mut c = RandomConvertor::stable(0, Algorithm::V1, 50, None);
+
+        c.reset("simple string");
+        assert_eq!(c.convert(String::from("simple string")), "siMple STrInG");
+    }
+
+    #[test]
+    fn hash_fnv1a() {
+        assert_eq!(hash(0, ""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash(0, "a"), 0xaf63_dc4c_8601_ec8c);
+    }
 }
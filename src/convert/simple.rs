@@ -1,60 +1,114 @@
-use {
-    crate::convert::Convertor,
-    std::iter::{once, Iterator},
+use crate::convert::{
+    casing::{self, Context, Rules},
+    convert_char_as_grapheme,
+    target::Targets,
+    Convertor, Settings,
 };
 
+/// A convertor which applies a fixed conversion to every grapheme cluster
+/// whose first character passes a filter, and leaves all others untouched.
+///
+/// When targets are configured, only the targeted characters are converted.
+/// Their chances are ignored, the conversion always being made.
 pub struct SimpleConvertor<'a> {
     filter: &'a dyn Fn(char) -> bool,
-    convert: &'a dyn Fn(char) -> Box<dyn Iterator<Item = char>>,
+    convert: &'a dyn Fn(&str, &Context, Rules) -> String,
+    rules: Rules,
+    targets: Targets,
 }
 
 #[allow(dead_code)]
 impl<'a> SimpleConvertor<'a> {
+    #[allow(clippy::new_ret_no_self)]
     fn new(
         filter: &'a dyn Fn(char) -> bool,
-        convert: &'a dyn Fn(char) -> Box<dyn Iterator<Item = char>>,
+        convert: &'a dyn Fn(&str, &Context, Rules) -> String,
     ) -> Box<dyn Convertor + 'a> {
-        Box::new(SimpleConvertor { filter, convert })
+        Box::new(SimpleConvertor {
+            filter,
+            convert,
+            rules: Rules::default(),
+            targets: Targets::default(),
+        })
     }
 
+    /// Convert every lowercase or titlecase letter to uppercase.
     pub fn uppercase() -> Box<dyn Convertor + 'a> {
         Self::new(
-            &|c: char| c.is_alphabetic() && c.is_lowercase(),
-            &|c: char| Box::new(c.to_uppercase()),
+            &|c: char| c.is_alphabetic() && (c.is_lowercase() || casing::is_titlecase(c)),
+            &casing::uppercase_grapheme,
         )
     }
 
+    /// Convert every uppercase or titlecase letter to lowercase.
     pub fn lowercase() -> Box<dyn Convertor + 'a> {
         Self::new(
-            &|c: char| c.is_alphabetic() && c.is_uppercase(),
-            &|c: char| Box::new(c.to_lowercase()),
+            &|c: char| c.is_alphabetic() && (c.is_uppercase() || casing::is_titlecase(c)),
+            &casing::lowercase_grapheme,
+        )
+    }
+
+    /// Make sentence punctuation angry, turning full stops into exclamation
+    /// marks and doubling up the exclamation and question marks, unless the
+    /// length is preserved.
+    pub fn punctuate() -> Box<dyn Convertor + 'a> {
+        Self::new(
+            &|c: char| matches!(c, '.' | '!' | '?'),
+            &|g: &str, _: &Context, rules: Rules| {
+                let mut chars = g.chars();
+                let angry = match chars.next() {
+                    Some('.') => "!",
+                    Some('!') if !rules.preserve_length => "!!",
+                    Some('?') if !rules.preserve_length => "?!",
+                    _ => return String::from(g),
+                };
+                angry.chars().chain(chars).collect()
+            },
         )
     }
 
+    /// Swap the case of every letter, titlecase letters being lowercased.
     pub fn reverse() -> Box<dyn Convertor + 'a> {
-        Self::new(&|c: char| c.is_alphabetic(), &|c: char| {
-            if c.is_lowercase() {
-                Box::new(c.to_uppercase())
-            } else {
-                Box::new(c.to_lowercase())
-            }
-        })
+        Self::new(
+            &|c: char| c.is_alphabetic(),
+            &|g: &str, context: &Context, rules: Rules| {
+                if g.starts_with(char::is_lowercase) {
+                    casing::uppercase_grapheme(g, context, rules)
+                } else {
+                    casing::lowercase_grapheme(g, context, rules)
+                }
+            },
+        )
     }
 }
 
 impl<'a> Convertor for SimpleConvertor<'a> {
-    fn convert_char(&mut self, c: char) -> Box<dyn Iterator<Item = char>> {
-        if (self.filter)(c) {
-            (self.convert)(c)
-        } else {
-            Box::new(once(c))
+    fn convert_grapheme(&mut self, g: &str, context: &Context) -> String {
+        match g.chars().next() {
+            Some(c) if (self.filter)(c) && self.targets.contains(c) => {
+                (self.convert)(g, context, self.rules)
+            }
+            _ => String::from(g),
         }
     }
+
+    fn convert_char_in(&mut self, c: char, context: &Context) -> Box<dyn Iterator<Item = char>> {
+        convert_char_as_grapheme(self, c, context)
+    }
+
+    fn configure(&mut self, settings: &Settings) {
+        self.rules = settings.rules();
+        self.targets = settings.targets.clone();
+    }
+
+    fn convert_char(&mut self, c: char) -> Box<dyn Iterator<Item = char>> {
+        self.convert_char_in(c, &Context::none())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use {super::*, crate::convert::casing::Locale};
 
     #[test]
     fn convert_string_to_upper() {
@@ -104,7 +158,7 @@ mod tests {
         let mut c = SimpleConvertor::uppercase();
 
         let mut i = c.convert_char('ǅ');
-        assert_eq!(i.next(), Some('ǅ'));
+        assert_eq!(i.next(), Some('Ǆ'));
         assert_eq!(i.next(), None);
     }
 
@@ -118,6 +172,50 @@ mod tests {
         assert_eq!(i.next(), None);
     }
 
+    #[test]
+    fn upper_string_preserving_length() {
+        let mut c = SimpleConvertor::uppercase();
+        c.configure(&Settings {
+            preserve_length: true,
+            ..Settings::default()
+        });
+
+        assert_eq!(c.convert(String::from("straße ﬁx")), "STRAẞE ﬁX");
+    }
+
+    #[test]
+    fn upper_string_targeting_vowels() {
+        let mut c = SimpleConvertor::uppercase();
+        c.configure(&Settings {
+            targets: Targets::new(vec!["vowels".parse().unwrap()]),
+            ..Settings::default()
+        });
+
+        assert_eq!(c.convert(String::from("simple string")), "sImplE strIng");
+    }
+
+    #[test]
+    fn lower_string_targeting_script() {
+        let mut c = SimpleConvertor::lowercase();
+        c.configure(&Settings {
+            targets: Targets::new(vec!["script:cyrillic".parse().unwrap()]),
+            ..Settings::default()
+        });
+
+        assert_eq!(c.convert(String::from("ABC АБВ")), "ABC абв");
+    }
+
+    #[test]
+    fn punctuate_string_preserving_length() {
+        let mut c = SimpleConvertor::punctuate();
+        c.configure(&Settings {
+            preserve_length: true,
+            ..Settings::default()
+        });
+
+        assert_eq!(c.convert(String::from("No. Why? Stop!")), "No! Why? Stop!");
+    }
+
     #[test]
     fn lower_char_from_lower() {
         let mut c = SimpleConvertor::lowercase();
@@ -159,7 +257,16 @@ mod tests {
         let mut c = SimpleConvertor::lowercase();
 
         let mut i = c.convert_char('ǅ');
-        assert_eq!(i.next(), Some('ǅ'));
+        assert_eq!(i.next(), Some('ǆ'));
+        assert_eq!(i.next(), None);
+    }
+
+    #[test]
+    fn reverse_char_from_fixed_ligature() {
+        let mut c = SimpleConvertor::reverse();
+
+        let mut i = c.convert_char('ǅ');
+        assert_eq!(i.next(), Some('ǆ'));
         assert_eq!(i.next(), None);
     }
 
@@ -189,4 +296,81 @@ mod tests {
         assert_eq!(i.next(), Some('1'));
         assert_eq!(i.next(), None);
     }
+
+    #[test]
+    fn punctuate_string() {
+        let mut c = SimpleConvertor::punctuate();
+
+        assert_eq!(
+            c.convert(String::from("No. Why? Stop!")),
+            "No! Why?! Stop!!"
+        );
+    }
+
+    #[test]
+    fn upper_string_keeps_combining_marks() {
+        let mut c = SimpleConvertor::uppercase();
+
+        assert_eq!(c.convert(String::from("α\u{345}")), "Α\u{345}");
+        assert_eq!(c.convert(String::from("e\u{301}")), "E\u{301}");
+    }
+
+    #[test]
+    fn upper_string_keeps_emoji_sequences() {
+        let mut c = SimpleConvertor::uppercase();
+
+        assert_eq!(
+            c.convert(String::from("hi 👩\u{200d}💻 🇬🇧")),
+            "HI 👩\u{200d}💻 🇬🇧"
+        );
+    }
+
+    #[test]
+    fn lower_string_with_final_sigma() {
+        let mut c = SimpleConvertor::lowercase();
+
+        assert_eq!(c.convert(String::from("ΟΔΟΣ ΣΟΦΙΑΣ")), "οδος σοφιας");
+    }
+
+    #[test]
+    fn upper_string_in_locale() {
+        let mut c = SimpleConvertor::uppercase();
+        c.configure(&Settings {
+            locale: Locale::Turkish,
+            ..Settings::default()
+        });
+
+        assert_eq!(c.convert(String::from("istanbul")), "İSTANBUL");
+    }
+
+    #[test]
+    fn lower_string_in_locale() {
+        let mut c = SimpleConvertor::lowercase();
+        c.configure(&Settings {
+            locale: Locale::Turkish,
+            ..Settings::default()
+        });
+
+        assert_eq!(c.convert(String::from("DİYARBAKIR")), "diyarbakır");
+        assert_eq!(c.convert(String::from("I\u{307}")), "i");
+    }
+
+    #[test]
+    fn reverse_string_in_locale() {
+        let mut c = SimpleConvertor::reverse();
+        c.configure(&Settings {
+            locale: Locale::Lithuanian,
+            ..Settings::default()
+        });
+
+        assert_eq!(c.convert(String::from("i\u{307}\u{301}")), "I\u{301}");
+        assert_eq!(c.convert(String::from("I\u{301}")), "i\u{307}\u{301}");
+    }
+
+    #[test]
+    fn reverse_string_with_final_sigma() {
+        let mut c = SimpleConvertor::reverse();
+
+        assert_eq!(c.convert(String::from("ΟΔΟΣ")), "οδος");
+    }
 }
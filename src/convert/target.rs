@@ -0,0 +1,304 @@
+//! Restrict convertors to some characters, such as the vowels or the letters
+//! of one script, each class with its own chance of being converted.
+
+use {
+    crate::convert::parse_percent,
+    anyhow::{Context, Error, Result},
+    regex::Regex,
+    std::{
+        fmt::{self, Display, Formatter},
+        str::FromStr,
+    },
+    unicode_normalization::char::decompose_canonical,
+    unicode_script::{Script, UnicodeScript},
+};
+
+/// The vowels of the Latin, Greek and Cyrillic alphabets, in lowercase and
+/// without accents.
+const VOWELS: &str = "aeiouæøœαεηιουωаеёиоуыэюяіїєә";
+
+/// A class of characters which can be targeted.
+#[derive(Debug, Clone)]
+pub enum Class {
+    /// Every letter.
+    Letters,
+    /// The letters which are vowels, with or without accents.
+    Vowels,
+    /// The letters which are not vowels.
+    Consonants,
+    /// The characters of a set.
+    Chars(String),
+    /// The characters of a Unicode script, such as Latin or Cyrillic.
+    Script(Script),
+    /// The characters matching a regular expression character class, such as
+    /// `[a-m]` or `\p{Greek}`, kept as it was given alongside the regex built
+    /// from it.
+    Regex(String, Regex),
+}
+
+fn is_vowel(c: char) -> bool {
+    let mut base = c;
+    decompose_canonical(c, |d| {
+        if base == c {
+            base = d;
+        }
+    });
+
+    base.to_lowercase().any(|lower| VOWELS.contains(lower))
+}
+
+impl Class {
+    /// Whether `c` belongs to the class.
+    pub fn matches(&self, c: char) -> bool {
+        match self {
+            Class::Letters => c.is_alphabetic(),
+            Class::Vowels => c.is_alphabetic() && is_vowel(c),
+            Class::Consonants => c.is_alphabetic() && !is_vowel(c),
+            Class::Chars(chars) => chars.contains(c),
+            Class::Script(script) => c.script() == *script,
+            Class::Regex(_, regex) => regex.is_match(c.encode_utf8(&mut [0; 4])),
+        }
+    }
+}
+
+impl PartialEq for Class {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Class::Regex(a, _), Class::Regex(b, _)) => a == b,
+            (Class::Chars(a), Class::Chars(b)) => a == b,
+            (Class::Script(a), Class::Script(b)) => a == b,
+            (a, b) => std::mem::discriminant(a) == std::mem::discriminant(b),
+        }
+    }
+}
+
+impl Eq for Class {}
+
+impl FromStr for Class {
+    type Err = Error;
+
+    /// Parse a class, one of `letters`, `vowels`, `consonants`, `chars:SET`,
+    /// `script:NAME` or `regex:CLASS`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            None | Some(("", _)) => match s {
+                "letters" => Ok(Class::Letters),
+                "vowels" => Ok(Class::Vowels),
+                "consonants" => Ok(Class::Consonants),
+                _ => Err(Error::msg(format!(
+                    "unknown class `{}`, expected one of: letters, vowels, consonants, \
+                     chars:SET, script:NAME, regex:CLASS",
+                    s
+                ))),
+            },
+            Some(("chars", chars)) if !chars.is_empty() => Ok(Class::Chars(String::from(chars))),
+            Some(("script", name)) => {
+                let mut capitalised = name.to_ascii_lowercase();
+                if let Some(first) = capitalised.get_mut(..1) {
+                    first.make_ascii_uppercase();
+                }
+
+                Script::from_full_name(name)
+                    .or_else(|| Script::from_full_name(&capitalised))
+                    .or_else(|| Script::from_short_name(&capitalised))
+                    .map(Class::Script)
+                    .ok_or_else(|| Error::msg(format!("unknown script `{}`", name)))
+            }
+            Some(("regex", class)) => Regex::new(&format!("^(?:{})$", class))
+                .map(|regex| Class::Regex(String::from(class), regex))
+                .with_context(|| format!("invalid regex `{}`", class)),
+            _ => Err(Error::msg(format!("invalid class `{}`", s))),
+        }
+    }
+}
+
+impl Display for Class {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Class::Letters => write!(f, "letters"),
+            Class::Vowels => write!(f, "vowels"),
+            Class::Consonants => write!(f, "consonants"),
+            Class::Chars(chars) => write!(f, "chars:{}", chars),
+            Class::Script(script) => write!(f, "script:{}", script.full_name()),
+            Class::Regex(class, _) => write!(f, "regex:{}", class),
+        }
+    }
+}
+
+/// A targeted class of characters, with the percentage chance of converting
+/// them when it differs from the convertor's own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub class: Class,
+    pub percent: Option<u8>,
+}
+
+impl FromStr for Target {
+    type Err = Error;
+
+    /// Parse a target given as `CLASS` or `CLASS=PERCENT`, such as
+    /// `vowels=80`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (class, percent) = match s.rsplit_once('=') {
+            Some((class, percent))
+                if !percent.is_empty() && percent.bytes().all(|b| b.is_ascii_digit()) =>
+            {
+                (class, Some(percent))
+            }
+            _ => (s, None),
+        };
+
+        Ok(Target {
+            class: class.parse()?,
+            percent: percent
+                .map(parse_percent)
+                .transpose()
+                .with_context(|| format!("invalid target `{}`", s))?,
+        })
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.percent {
+            Some(percent) => write!(f, "{}={}", self.class, percent),
+            None => write!(f, "{}", self.class),
+        }
+    }
+}
+
+/// The classes of characters a convertor is restricted to.
+///
+/// With no targets every character is converted as usual. Otherwise only the
+/// characters in one of the classes are, the first class a character belongs
+/// to giving its chance of being converted.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Targets(Vec<Target>);
+
+impl Targets {
+    /// Target the given classes, in order of precedence.
+    pub fn new(targets: Vec<Target>) -> Self {
+        Targets(targets)
+    }
+
+    /// Whether `c` is targeted.
+    pub fn contains(&self, c: char) -> bool {
+        self.0.is_empty() || self.find(c).is_some()
+    }
+
+    /// The chance of converting `c`, if it is targeted by a class with a
+    /// chance of its own.
+    pub fn percent(&self, c: char) -> Option<u8> {
+        self.find(c).and_then(|target| target.percent)
+    }
+
+    fn find(&self, c: char) -> Option<&Target> {
+        self.0.iter().find(|target| target.class.matches(c))
+    }
+}
+
+impl From<Vec<Target>> for Targets {
+    fn from(targets: Vec<Target>) -> Self {
+        Targets::new(targets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vowels_and_consonants() {
+        assert!(Class::Vowels.matches('a'));
+        assert!(Class::Vowels.matches('É'));
+        assert!(Class::Vowels.matches('ю'));
+        assert!(!Class::Vowels.matches('b'));
+        assert!(Class::Consonants.matches('b'));
+        assert!(Class::Consonants.matches('Ç'));
+        assert!(!Class::Consonants.matches('e'));
+        assert!(!Class::Consonants.matches('1'));
+    }
+
+    #[test]
+    fn class_from_str() {
+        assert_eq!("vowels".parse::<Class>().unwrap(), Class::Vowels);
+        assert_eq!(
+            "chars:xyz".parse::<Class>().unwrap(),
+            Class::Chars(String::from("xyz"))
+        );
+        assert_eq!(
+            "script:cyrillic".parse::<Class>().unwrap(),
+            Class::Script(Script::Cyrillic)
+        );
+        assert_eq!(
+            "script:Latn".parse::<Class>().unwrap(),
+            Class::Script(Script::Latin)
+        );
+        assert!("script:klingon".parse::<Class>().is_err());
+        assert!("regex:[a-".parse::<Class>().is_err());
+        assert!("shouting".parse::<Class>().is_err());
+    }
+
+    #[test]
+    fn class_matches() {
+        let latin: Class = "script:latin".parse().unwrap();
+        assert!(latin.matches('z'));
+        assert!(!latin.matches('ж'));
+
+        let regex: Class = "regex:[a-m]".parse().unwrap();
+        assert!(regex.matches('c'));
+        assert!(!regex.matches('z'));
+
+        let chars: Class = "chars:xyz".parse().unwrap();
+        assert!(chars.matches('y'));
+        assert!(!chars.matches('a'));
+    }
+
+    #[test]
+    fn target_from_str() {
+        let target: Target = "vowels=80".parse().unwrap();
+        assert_eq!(target.class, Class::Vowels);
+        assert_eq!(target.percent, Some(80));
+
+        let target: Target = "chars:=+".parse().unwrap();
+        assert_eq!(target.class, Class::Chars(String::from("=+")));
+        assert_eq!(target.percent, None);
+
+        assert!("vowels=101".parse::<Target>().is_err());
+    }
+
+    #[test]
+    fn target_display() {
+        for s in &[
+            "vowels=80",
+            "chars:xyz",
+            "script:Cyrillic=10",
+            "regex:[a-m]",
+            "regex:a|b",
+            "regex:\\p{Greek}=20",
+        ] {
+            assert_eq!(s.parse::<Target>().unwrap().to_string(), *s);
+        }
+    }
+
+    #[test]
+    fn targets_precedence() {
+        let targets = Targets::new(vec![
+            "vowels=80".parse().unwrap(),
+            "letters=30".parse().unwrap(),
+        ]);
+
+        assert_eq!(targets.percent('a'), Some(80));
+        assert_eq!(targets.percent('b'), Some(30));
+        assert!(!targets.contains('1'));
+    }
+
+    #[test]
+    fn no_targets_contains_everything() {
+        let targets = Targets::default();
+
+        assert!(targets.contains('a'));
+        assert!(targets.contains('1'));
+        assert_eq!(targets.percent('a'), None);
+    }
+}
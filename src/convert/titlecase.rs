@@ -0,0 +1,122 @@
+use {
+    crate::convert::{
+        casing::{self, Context, Rules},
+        convert_char_as_grapheme, Convertor, Settings,
+    },
+    std::iter::Iterator,
+};
+
+/// A convertor which gives the first letter of every word its titlecase form
+/// and lowercases the rest, so `ǆEMAL` becomes `ǅemal`.
+///
+/// Words start at any letter or digit, and apostrophes and other
+/// case-ignorable characters do not end them, so `DON'T` becomes `Don't` and
+/// `2ND` becomes `2nd`.
+#[derive(Debug, Default)]
+pub struct TitlecaseConvertor {
+    in_word: bool,
+    rules: Rules,
+}
+
+impl TitlecaseConvertor {
+    /// Create a titlecase convertor.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new() -> Box<dyn Convertor> {
+        Box::new(TitlecaseConvertor::default())
+    }
+}
+
+impl Convertor for TitlecaseConvertor {
+    fn reset(&mut self, _record: &str) {
+        self.in_word = false;
+    }
+
+    fn configure(&mut self, settings: &Settings) {
+        self.rules = settings.rules();
+    }
+
+    fn convert_grapheme(&mut self, g: &str, context: &Context) -> String {
+        match g.chars().next() {
+            Some(c) if casing::is_cased(c) => {
+                let first = !self.in_word;
+                self.in_word = true;
+
+                if first {
+                    casing::titlecase_grapheme(g, context, self.rules)
+                } else {
+                    casing::lowercase_grapheme(g, context, self.rules)
+                }
+            }
+            Some(c) => {
+                self.in_word =
+                    c.is_alphanumeric() || (self.in_word && casing::is_case_ignorable(c));
+                String::from(g)
+            }
+            None => String::new(),
+        }
+    }
+
+    fn convert_char_in(&mut self, c: char, context: &Context) -> Box<dyn Iterator<Item = char>> {
+        convert_char_as_grapheme(self, c, context)
+    }
+
+    fn convert_char(&mut self, c: char) -> Box<dyn Iterator<Item = char>> {
+        self.convert_char_in(c, &Context::none())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::convert::casing::Locale};
+
+    #[test]
+    fn titlecase_convert() {
+        let mut c = TitlecaseConvertor::new();
+
+        assert_eq!(
+            c.convert(String::from("a LITTLE bit of text")),
+            "A Little Bit Of Text"
+        );
+    }
+
+    #[test]
+    fn titlecase_convert_digraphs() {
+        let mut c = TitlecaseConvertor::new();
+
+        assert_eq!(
+            c.convert(String::from("ǆEMAL ǄEMAL ǉubljana")),
+            "ǅemal ǅemal ǈubljana"
+        );
+    }
+
+    #[test]
+    fn titlecase_convert_keeps_words_whole() {
+        let mut c = TitlecaseConvertor::new();
+
+        assert_eq!(
+            c.convert(String::from("DON'T stop-GO 2nd")),
+            "Don't Stop-Go 2nd"
+        );
+    }
+
+    #[test]
+    fn titlecase_convert_in_locale() {
+        let mut c = TitlecaseConvertor::new();
+        c.configure(&Settings {
+            locale: Locale::Turkish,
+            ..Settings::default()
+        });
+
+        assert_eq!(c.convert(String::from("izmir IĞDIR")), "İzmir Iğdır");
+    }
+
+    #[test]
+    fn titlecase_convert_restarts_each_record() {
+        let mut c = TitlecaseConvertor::new();
+
+        c.reset("one");
+        assert_eq!(c.convert(String::from("one")), "One");
+        c.reset("two");
+        assert_eq!(c.convert(String::from("two")), "Two");
+    }
+}
@@ -0,0 +1,690 @@
+//! Drive a [`Convertor`] over a stream of records, such as lines.
+
+use {
+    crate::convert::Convertor,
+    anyhow::{Context, Error},
+    regex::bytes::Regex,
+    std::{
+        borrow::Cow,
+        error,
+        fmt::{self, Display, Formatter},
+        io::{self, BufRead, Write},
+        str::FromStr,
+    },
+};
+
+/// How the input is split into records, each converted on its own.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Separator {
+    /// Lines, ended by `\n` or `\r\n`.
+    #[default]
+    Line,
+    /// Records ended by a sequence of bytes, such as a NUL.
+    Bytes(Vec<u8>),
+    /// Paragraphs, ended by one or more blank lines.
+    Paragraph,
+}
+
+impl Separator {
+    /// Records ended by a NUL byte.
+    pub fn null() -> Self {
+        Separator::Bytes(vec![0])
+    }
+
+    /// The terminator ending a record, as written after a last record which
+    /// had none when line endings are normalized.
+    pub fn terminator(&self) -> &[u8] {
+        match self {
+            Separator::Line | Separator::Paragraph => b"\n",
+            Separator::Bytes(bytes) => bytes,
+        }
+    }
+}
+
+impl FromStr for Separator {
+    type Err = Error;
+
+    /// Parse a separator, in which `\0`, `\n`, `\r`, `\t` and `\\` are
+    /// escapes. A newline gives [`Separator::Line`], and an empty separator
+    /// gives [`Separator::Paragraph`].
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut bytes = Vec::with_capacity(s.len());
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            let c = match c {
+                '\\' => match chars.next() {
+                    Some('0') => '\0',
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some('\\') => '\\',
+                    Some(other) => return Err(Error::msg(format!("unknown escape `\\{}`", other))),
+                    None => return Err(Error::msg("separator ends with a lone `\\`")),
+                },
+                c => c,
+            };
+            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+
+        Ok(match bytes.as_slice() {
+            b"" => Separator::Paragraph,
+            b"\n" => Separator::Line,
+            _ => Separator::Bytes(bytes),
+        })
+    }
+}
+
+/// How the end of each record is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEndings {
+    /// Write each record's original terminator, such as `\n` or `\r\n`, and
+    /// none after a last record which had none.
+    #[default]
+    Keep,
+    /// Write every `\r\n` as `\n`, and end the last record like the others
+    /// when it had no terminator.
+    Lf,
+}
+
+impl LineEndings {
+    fn apply<'a>(self, ending: &'a [u8], separator: &'a Separator) -> Cow<'a, [u8]> {
+        match self {
+            LineEndings::Keep => Cow::Borrowed(ending),
+            LineEndings::Lf if ending.is_empty() => Cow::Borrowed(separator.terminator()),
+            LineEndings::Lf => match separator {
+                Separator::Bytes(_) => Cow::Borrowed(ending),
+                _ => Cow::Owned(ending.iter().copied().filter(|&b| b != b'\r').collect()),
+            },
+        }
+    }
+}
+
+/// What is done with input which is not valid UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Utf8Errors {
+    /// Stop the conversion with an [`InvalidUtf8`] error.
+    #[default]
+    Strict,
+    /// Replace each invalid sequence with U+FFFD REPLACEMENT CHARACTER.
+    Lossy,
+    /// Write each invalid sequence unchanged, converting the text around it.
+    Bytes,
+}
+
+/// An address picking out records, in the spirit of sed.
+#[derive(Debug, Clone)]
+pub enum Address {
+    /// The record with the given number, counting from 1.
+    Record(u64),
+    /// Every record whose content matches a regex.
+    Pattern(Regex),
+}
+
+impl Address {
+    fn matches(&self, number: u64, content: &[u8]) -> bool {
+        match self {
+            Address::Record(record) => *record == number,
+            Address::Pattern(regex) => regex.is_match(content),
+        }
+    }
+
+    /// Parse the address at the start of `s`, returning it with the rest of
+    /// `s`.
+    fn parse(s: &str) -> anyhow::Result<(Self, &str)> {
+        if let Some(rest) = s.strip_prefix('/') {
+            let mut escaped = false;
+            for (i, c) in rest.char_indices() {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '/' => {
+                        let pattern = rest[..i].replace("\\/", "/");
+                        let regex = Regex::new(&pattern)
+                            .with_context(|| format!("invalid regex `{}`", pattern))?;
+                        return Ok((Address::Pattern(regex), &rest[i + 1..]));
+                    }
+                    _ => {}
+                }
+            }
+
+            Err(Error::msg(format!("unterminated regex in `{}`", s)))
+        } else {
+            let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+            match s[..end].parse() {
+                Ok(0) => Err(Error::msg("line numbers start at 1")),
+                Ok(number) => Ok((Address::Record(number), &s[end..])),
+                Err(_) => Err(Error::msg(format!(
+                    "invalid address `{}`, expected a line number or a /regex/",
+                    s
+                ))),
+            }
+        }
+    }
+}
+
+/// The records to convert, given by an address or a range of two.
+///
+/// A range starts at a record matching its first address and ends at the
+/// next record matching its second, both included. As in sed, a range ending
+/// at a line number which has already passed only covers its first record,
+/// and a range starting at a regex starts again at the next match after it
+/// ends.
+#[derive(Debug, Clone)]
+pub struct Lines {
+    start: Address,
+    end: Option<Address>,
+}
+
+impl Lines {
+    /// Whether the record with the given `number` and `content` is selected,
+    /// `open` tracking whether a range has started and not yet ended.
+    fn selects(&self, open: &mut bool, number: u64, content: &[u8]) -> bool {
+        match &self.end {
+            None => self.start.matches(number, content),
+            Some(end) if *open => {
+                *open = match end {
+                    Address::Record(last) => number < *last,
+                    Address::Pattern(regex) => !regex.is_match(content),
+                };
+                true
+            }
+            Some(end) if self.start.matches(number, content) => {
+                *open = match end {
+                    Address::Record(last) => number < *last,
+                    Address::Pattern(_) => true,
+                };
+                true
+            }
+            Some(_) => false,
+        }
+    }
+}
+
+impl FromStr for Lines {
+    type Err = Error;
+
+    /// Parse an address, a line number such as `10` or a regex such as
+    /// `/^ERROR/`, or a range of two separated by `,` or `-`, such as `10-20`
+    /// or `/BEGIN/,/END/`. A `/` in a regex is escaped as `\/`.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (start, rest) = Address::parse(s)?;
+        let end = match rest.strip_prefix(&[',', '-'][..]) {
+            Some(rest) => match Address::parse(rest)? {
+                (end, "") => Some(end),
+                (_, extra) => return Err(Error::msg(format!("unexpected `{}` in `{}`", extra, s))),
+            },
+            None if rest.is_empty() => None,
+            None => return Err(Error::msg(format!("unexpected `{}` in `{}`", rest, s))),
+        };
+
+        Ok(Lines { start, end })
+    }
+}
+
+/// Options for reading and writing the records of a conversion.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub separator: Separator,
+    pub line_endings: LineEndings,
+    pub utf8_errors: Utf8Errors,
+    /// The records to convert, all of them when `None`.
+    pub lines: Option<Lines>,
+}
+
+/// The position of the first invalid UTF-8 sequence in a strict conversion.
+///
+/// It is returned as the inner error of an [`io::Error`] of kind
+/// [`InvalidData`](io::ErrorKind::InvalidData).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidUtf8 {
+    /// The offset of the sequence from the start of the input, in bytes.
+    pub offset: u64,
+    /// The line of the sequence, counting from 1.
+    pub line: u64,
+}
+
+impl Display for InvalidUtf8 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid UTF-8 at byte offset {}, line {}",
+            self.offset, self.line
+        )
+    }
+}
+
+impl error::Error for InvalidUtf8 {}
+
+/// Count the lines ended in `bytes`.
+fn count_lines(bytes: &[u8]) -> u64 {
+    bytes.iter().filter(|&&b| b == b'\n').count() as u64
+}
+
+/// Strip the terminator, `\n` or `\r\n`, from the end of `line`.
+fn strip_newline(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\r\n")
+        .or_else(|| line.strip_suffix(b"\n"))
+        .unwrap_or(line)
+}
+
+/// Whether the next line to be read from `input` is blank.
+fn next_is_blank<R: BufRead>(input: &mut R) -> io::Result<bool> {
+    let buffer = input.fill_buf()?;
+    Ok(buffer.starts_with(b"\n") || buffer.starts_with(b"\r\n"))
+}
+
+/// Read the next record from `input` into `record`, terminator included,
+/// returning the length of its content, or `None` at the end of the input.
+fn read_record<R: BufRead>(
+    input: &mut R,
+    separator: &Separator,
+    record: &mut Vec<u8>,
+) -> io::Result<Option<usize>> {
+    let content = match separator {
+        Separator::Line => {
+            input.read_until(b'\n', record)?;
+            strip_newline(record).len()
+        }
+        Separator::Bytes(bytes) => {
+            let last = *bytes.last().expect("a separator is never empty");
+            while input.read_until(last, record)? > 0 && !record.ends_with(bytes) {}
+            record
+                .strip_suffix(bytes.as_slice())
+                .unwrap_or(record)
+                .len()
+        }
+        Separator::Paragraph => {
+            while !input.fill_buf()?.is_empty() && !next_is_blank(input)? {
+                input.read_until(b'\n', record)?;
+            }
+            let content = strip_newline(record).len();
+            while next_is_blank(input)? {
+                input.read_until(b'\n', record)?;
+            }
+            content
+        }
+    };
+
+    Ok(if record.is_empty() {
+        None
+    } else {
+        Some(content)
+    })
+}
+
+/// Read `input` line by line, convert each line and write it to `output`
+/// with its original line ending. The convertor is
+/// [reset](Convertor::reset) before each line.
+pub fn convert<R, W>(convertor: &mut dyn Convertor, input: R, output: W) -> io::Result<()>
+where
+    R: BufRead,
+    W: Write,
+{
+    convert_with(convertor, input, output, &Options::default())
+}
+
+/// Read `input` one record at a time, convert each record and write it to
+/// `output`, splitting and ending the records as set by the `options`. The
+/// convertor is [reset](Convertor::reset) before each record.
+///
+/// When the `options` select [lines](Lines), the content of the other records
+/// is written unchanged, without resetting the convertor, so that its state
+/// carries on from one converted record to the next.
+///
+/// Only the record terminators are treated specially, so a lone `\r` is kept
+/// as part of its line. Input which is not valid UTF-8, in a selected record
+/// or not, is handled as set by the `options`, by default stopping the
+/// conversion with an [`InvalidUtf8`] error once the records before it have
+/// been written. The first error raised
+/// while reading or writing stops the conversion and is returned.
+pub fn convert_with<R, W>(
+    convertor: &mut dyn Convertor,
+    mut input: R,
+    mut output: W,
+    options: &Options,
+) -> io::Result<()>
+where
+    R: BufRead,
+    W: Write,
+{
+    let mut buffer = Vec::new();
+    let mut offset = 0;
+    let mut line = 1;
+    let mut number = 0;
+    let mut open = false;
+    while let Some(length) = read_record(&mut input, &options.separator, &mut buffer)? {
+        let (content, ending) = buffer.split_at(length);
+        number += 1;
+        let selected = options
+            .lines
+            .as_ref()
+            .is_none_or(|lines| lines.selects(&mut open, number, content));
+        match (std::str::from_utf8(content), options.utf8_errors) {
+            (Ok(_), _) if !selected => output.write_all(content)?,
+            (Ok(record), _) => {
+                convertor.reset(record);
+                output.write_all(convertor.convert(String::from(record)).as_bytes())?;
+            }
+            (Err(error), Utf8Errors::Strict) => {
+                let valid = &content[..error.valid_up_to()];
+                output.flush()?;
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    InvalidUtf8 {
+                        offset: offset + valid.len() as u64,
+                        line: line + count_lines(valid),
+                    },
+                ));
+            }
+            (Err(_), Utf8Errors::Lossy) if !selected => {
+                output.write_all(String::from_utf8_lossy(content).as_bytes())?
+            }
+            (Err(_), Utf8Errors::Lossy) => {
+                let record = String::from_utf8_lossy(content).into_owned();
+                convertor.reset(&record);
+                output.write_all(convertor.convert(record).as_bytes())?;
+            }
+            (Err(_), Utf8Errors::Bytes) if !selected => output.write_all(content)?,
+            (Err(_), Utf8Errors::Bytes) => {
+                convertor.reset(&String::from_utf8_lossy(content));
+                for chunk in content.utf8_chunks() {
+                    output.write_all(convertor.convert(String::from(chunk.valid())).as_bytes())?;
+                    output.write_all(chunk.invalid())?;
+                }
+            }
+        }
+        output.write_all(&options.line_endings.apply(ending, &options.separator))?;
+
+        offset += buffer.len() as u64;
+        line += count_lines(&buffer);
+        buffer.clear();
+    }
+
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::convert::{
+            alternate::{Case, Restart},
+            Conversion,
+        },
+        std::io::Cursor,
+    };
+
+    fn convert_str(input: &str, options: &Options) -> Vec<u8> {
+        let mut output = Vec::new();
+        let mut convertor = Conversion::Uppercase.convertor();
+
+        convert_with(convertor.as_mut(), Cursor::new(input), &mut output, options).unwrap();
+
+        output
+    }
+
+    #[test]
+    fn convert_lines() {
+        let mut output = Vec::new();
+        let mut convertor = Conversion::Uppercase.convertor();
+
+        convert(
+            convertor.as_mut(),
+            Cursor::new("first line\nsecond line"),
+            &mut output,
+        )
+        .unwrap();
+
+        assert_eq!(output, b"FIRST LINE\nSECOND LINE");
+    }
+
+    #[test]
+    fn convert_keeps_line_endings() {
+        let options = Options::default();
+
+        assert_eq!(convert_str("one\r\ntwo\n", &options), b"ONE\r\nTWO\n");
+        assert_eq!(convert_str("one\rtwo\r", &options), b"ONE\rTWO\r");
+        assert_eq!(convert_str("\n\r\n", &options), b"\n\r\n");
+        assert_eq!(convert_str("", &options), b"");
+    }
+
+    #[test]
+    fn convert_with_lf_line_endings() {
+        let options = Options {
+            line_endings: LineEndings::Lf,
+            ..Options::default()
+        };
+
+        assert_eq!(convert_str("one\r\ntwo", &options), b"ONE\nTWO\n");
+    }
+
+    fn convert_bytes(input: &[u8], utf8_errors: Utf8Errors) -> io::Result<Vec<u8>> {
+        let options = Options {
+            utf8_errors,
+            ..Options::default()
+        };
+        let mut output = Vec::new();
+        let mut convertor = Conversion::Uppercase.convertor();
+
+        convert_with(
+            convertor.as_mut(),
+            Cursor::new(input),
+            &mut output,
+            &options,
+        )?;
+
+        Ok(output)
+    }
+
+    #[test]
+    fn convert_invalid_utf8_strictly() {
+        let error = convert_bytes(b"one\ntw\xffo\nthree\n", Utf8Errors::Strict).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.get_ref().unwrap().downcast_ref::<InvalidUtf8>(),
+            Some(&InvalidUtf8 { offset: 6, line: 2 })
+        );
+        assert_eq!(error.to_string(), "invalid UTF-8 at byte offset 6, line 2");
+    }
+
+    #[test]
+    fn convert_invalid_utf8_lossily() {
+        assert_eq!(
+            convert_bytes(b"tw\xffo\r\n", Utf8Errors::Lossy).unwrap(),
+            "TW\u{fffd}O\r\n".as_bytes()
+        );
+    }
+
+    #[test]
+    fn convert_invalid_utf8_as_bytes() {
+        assert_eq!(
+            convert_bytes(b"caf\xe9 au lait\n", Utf8Errors::Bytes).unwrap(),
+            b"CAF\xe9 AU LAIT\n"
+        );
+    }
+
+    #[test]
+    fn convert_null_separated() {
+        let options = Options {
+            separator: Separator::null(),
+            ..Options::default()
+        };
+
+        assert_eq!(
+            convert_str("one\0two\nlines\0three", &options),
+            b"ONE\0TWO\nLINES\0THREE"
+        );
+    }
+
+    #[test]
+    fn convert_multi_byte_separated() {
+        let options = Options {
+            separator: "--".parse().unwrap(),
+            line_endings: LineEndings::Lf,
+            ..Options::default()
+        };
+
+        assert_eq!(convert_str("a-b--c", &options), b"A-B--C--");
+    }
+
+    #[test]
+    fn convert_paragraphs() {
+        let options = Options {
+            separator: Separator::Paragraph,
+            ..Options::default()
+        };
+
+        assert_eq!(
+            convert_str("\none\ntwo\n\n\r\nthree\n", &options),
+            b"\nONE\nTWO\n\n\r\nTHREE\n"
+        );
+    }
+
+    #[test]
+    fn convert_resets_each_paragraph() {
+        let options = Options {
+            separator: Separator::Paragraph,
+            ..Options::default()
+        };
+        let mut output = Vec::new();
+        let mut convertor = Conversion::Titlecase.convertor();
+
+        convert_with(
+            convertor.as_mut(),
+            Cursor::new("one\ntwo\n\nthree\n"),
+            &mut output,
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(output, b"One\nTwo\n\nThree\n");
+    }
+
+    #[test]
+    fn separator_from_str() {
+        assert_eq!("\\0".parse::<Separator>().unwrap(), Separator::null());
+        assert_eq!("\\n".parse::<Separator>().unwrap(), Separator::Line);
+        assert_eq!("".parse::<Separator>().unwrap(), Separator::Paragraph);
+        assert_eq!(
+            "\\t|".parse::<Separator>().unwrap(),
+            Separator::Bytes(b"\t|".to_vec())
+        );
+        assert!("\\q".parse::<Separator>().is_err());
+    }
+
+    #[test]
+    fn convert_resets_with_line_content() {
+        struct Records(Vec<String>);
+
+        impl Convertor for Records {
+            fn reset(&mut self, record: &str) {
+                self.0.push(String::from(record));
+            }
+
+            fn convert_char(&mut self, c: char) -> Box<dyn Iterator<Item = char>> {
+                Box::new(std::iter::once(c))
+            }
+        }
+
+        let mut records = Records(Vec::new());
+        convert(&mut records, Cursor::new("a\r\nb\n"), Vec::new()).unwrap();
+
+        assert_eq!(records.0, ["a", "b"]);
+
+        let options = Options {
+            separator: Separator::Paragraph,
+            ..Options::default()
+        };
+        let mut records = Records(Vec::new());
+        convert_with(&mut records, Cursor::new("a\nb\n\nc"), Vec::new(), &options).unwrap();
+
+        assert_eq!(records.0, ["a\nb", "c"]);
+    }
+
+    fn convert_lines_at(input: &str, lines: &str) -> Vec<u8> {
+        let options = Options {
+            lines: Some(lines.parse().unwrap()),
+            ..Options::default()
+        };
+
+        convert_str(input, &options)
+    }
+
+    #[test]
+    fn convert_addressed_lines() {
+        let input = "one\ntwo\nthree\nfour\n";
+
+        assert_eq!(convert_lines_at(input, "2"), b"one\nTWO\nthree\nfour\n");
+        assert_eq!(convert_lines_at(input, "2-3"), b"one\nTWO\nTHREE\nfour\n");
+        assert_eq!(convert_lines_at(input, "3,1"), b"one\ntwo\nTHREE\nfour\n");
+        assert_eq!(convert_lines_at(input, "/o/"), b"ONE\nTWO\nthree\nFOUR\n");
+        assert_eq!(convert_lines_at(input, "3-/x/"), b"one\ntwo\nTHREE\nFOUR\n");
+    }
+
+    #[test]
+    fn convert_repeated_pattern_ranges() {
+        assert_eq!(
+            convert_lines_at("a\nBEGIN b\nc\nEND d\ne\nBEGIN f\ng\n", "/BEGIN/,/END/"),
+            b"a\nBEGIN B\nC\nEND D\ne\nBEGIN F\nG\n"
+        );
+    }
+
+    #[test]
+    fn convert_addressed_lines_keeps_state() {
+        let options = Options {
+            lines: Some("/^x/".parse().unwrap()),
+            ..Options::default()
+        };
+        let mut output = Vec::new();
+        let mut convertor = Conversion::Alternate(Case::Upper, Restart::Never).convertor();
+
+        convert_with(
+            convertor.as_mut(),
+            Cursor::new("xab\nskip\nxcd\n"),
+            &mut output,
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(output, b"XaB\nskip\nxCd\n");
+    }
+
+    #[test]
+    fn convert_unselected_invalid_utf8() {
+        let convert = |utf8_errors| {
+            let options = Options {
+                utf8_errors,
+                lines: Some("2".parse().unwrap()),
+                ..Options::default()
+            };
+            let mut output = Vec::new();
+            let mut convertor = Conversion::Uppercase.convertor();
+            convert_with(
+                convertor.as_mut(),
+                Cursor::new(&b"caf\xe9\nau lait\n"[..]),
+                &mut output,
+                &options,
+            )
+            .map(|_| output)
+        };
+
+        let error = convert(Utf8Errors::Strict).unwrap_err();
+        assert_eq!(error.to_string(), "invalid UTF-8 at byte offset 3, line 1");
+        assert_eq!(
+            convert(Utf8Errors::Lossy).unwrap(),
+            "caf\u{fffd}\nAU LAIT\n".as_bytes()
+        );
+        assert_eq!(convert(Utf8Errors::Bytes).unwrap(), b"caf\xe9\nAU LAIT\n");
+    }
+
+    #[test]
+    fn lines_from_str() {
+        assert!("/a\\/b/".parse::<Lines>().unwrap().start.matches(1, b"a/b"));
+        assert!("0".parse::<Lines>().is_err());
+        assert!("x".parse::<Lines>().is_err());
+        assert!("/a".parse::<Lines>().is_err());
+        assert!("/(/".parse::<Lines>().is_err());
+        assert!("1-2-3".parse::<Lines>().is_err());
+    }
+}
@@ -0,0 +1,602 @@
+//! Read and write text in encodings other than UTF-8, such as Windows-1252,
+//! Shift_JIS or UTF-16.
+//!
+//! A [`Decoder`] turns its input into UTF-8 for the [`driver`](crate::driver),
+//! and an [`Encoder`] turns the UTF-8 written to it back into an encoding.
+
+use {
+    crate::driver::Utf8Errors,
+    anyhow::{Error, Result},
+    encoding_rs::{DecoderResult, EncoderResult, Encoding, UTF_16BE, UTF_16LE, UTF_8},
+    std::{
+        error,
+        fmt::{self, Display, Formatter},
+        io::{self, BufRead, Chain, Cursor, Read, Write},
+    },
+};
+
+/// How many bytes from the end of each read a [`Decoder`] keeps, enough for
+/// the longest malformed sequence in any encoding.
+const TAIL_LENGTH: usize = 4;
+
+/// Look up an encoding by one of its labels, such as `utf-16le`, `latin1`,
+/// `windows-1252` or `shift_jis`.
+pub fn parse_encoding(label: &str) -> Result<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes())
+        .ok_or_else(|| Error::msg(format!("unknown encoding `{}`", label)))
+}
+
+/// The byte order mark of `encoding`, if it has one.
+fn bom(encoding: &'static Encoding) -> &'static [u8] {
+    if encoding == UTF_8 {
+        b"\xef\xbb\xbf"
+    } else if encoding == UTF_16LE {
+        b"\xff\xfe"
+    } else if encoding == UTF_16BE {
+        b"\xfe\xff"
+    } else {
+        b""
+    }
+}
+
+/// The position of the first malformed sequence in input which is decoded
+/// strictly.
+///
+/// It is returned as the inner error of an [`io::Error`] of kind
+/// [`InvalidData`](io::ErrorKind::InvalidData), once the text decoded before
+/// it has been read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Malformed {
+    /// The offset of the sequence from the start of the input, in bytes.
+    pub offset: u64,
+    /// The line of the sequence, counting from 1.
+    pub line: u64,
+    /// The encoding of the input.
+    pub encoding: &'static Encoding,
+}
+
+impl Display for Malformed {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid {} at byte offset {}, line {}",
+            self.encoding.name(),
+            self.offset,
+            self.line
+        )
+    }
+}
+
+impl error::Error for Malformed {}
+
+/// A reader which decodes its input to UTF-8.
+///
+/// A byte order mark at the start of the input takes precedence over the
+/// given encoding, and is removed. UTF-8 input is passed on as it is, so that
+/// invalid sequences are left for the driver to handle, while malformed input
+/// in any other encoding is handled as the driver handles invalid UTF-8: it
+/// fails with a [`Malformed`] error, is replaced with U+FFFD REPLACEMENT
+/// CHARACTER, or is passed on unchanged.
+pub struct Decoder<R> {
+    input: Chain<Cursor<Vec<u8>>, R>,
+    encoding: &'static Encoding,
+    decoder: Option<encoding_rs::Decoder>,
+    errors: Utf8Errors,
+    decoded: Vec<u8>,
+    position: usize,
+    finished: bool,
+    bom: bool,
+    offset: u64,
+    lines: u64,
+    malformed: Option<Malformed>,
+    tail: Vec<u8>,
+}
+
+impl<R: BufRead> Decoder<R> {
+    /// Decode `input` from `encoding`, or from UTF-8 when there is neither an
+    /// encoding nor a byte order mark, failing on malformed input.
+    pub fn new(input: R, encoding: Option<&'static Encoding>) -> io::Result<Self> {
+        Decoder::with_errors(input, encoding, Utf8Errors::Strict)
+    }
+
+    /// Decode `input` from `encoding`, handling malformed input in any
+    /// encoding other than UTF-8 as set by `errors`.
+    pub fn with_errors(
+        mut input: R,
+        encoding: Option<&'static Encoding>,
+        errors: Utf8Errors,
+    ) -> io::Result<Self> {
+        let mut start = Vec::with_capacity(3);
+        (&mut input).take(3).read_to_end(&mut start)?;
+
+        let (encoding, bom) = match Encoding::for_bom(&start) {
+            Some((encoding, length)) => {
+                start.drain(..length);
+                (encoding, length)
+            }
+            None => (encoding.unwrap_or(UTF_8), 0),
+        };
+
+        Ok(Decoder {
+            input: Cursor::new(start).chain(input),
+            encoding,
+            decoder: if encoding == UTF_8 {
+                None
+            } else {
+                Some(encoding.new_decoder_without_bom_handling())
+            },
+            errors,
+            decoded: Vec::new(),
+            position: 0,
+            finished: false,
+            bom: bom > 0,
+            offset: bom as u64,
+            lines: 0,
+            malformed: None,
+            tail: Vec::with_capacity(TAIL_LENGTH),
+        })
+    }
+
+    /// The encoding of the input.
+    pub fn encoding(&self) -> &'static Encoding {
+        self.encoding
+    }
+
+    /// Whether the input started with a byte order mark.
+    pub fn has_bom(&self) -> bool {
+        self.bom
+    }
+
+    /// The underlying reader.
+    pub fn get_ref(&self) -> &R {
+        self.input.get_ref().1
+    }
+}
+
+impl<R: BufRead> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let length = self.fill_buf()?.read(buf)?;
+        self.consume(length);
+        Ok(length)
+    }
+}
+
+impl<R: BufRead> BufRead for Decoder<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let decoder = match &mut self.decoder {
+            Some(decoder) => decoder,
+            None => return self.input.fill_buf(),
+        };
+
+        while self.position == self.decoded.len() && !self.finished {
+            if let Some(malformed) = self.malformed.take() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, malformed));
+            }
+
+            let source = self.input.fill_buf()?;
+            let last = source.is_empty();
+            let length = decoder
+                .max_utf8_buffer_length_without_replacement(source.len())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::OutOfMemory, "input too long"))?;
+
+            self.decoded.resize(length, 0);
+            let (result, read, written) =
+                decoder.decode_to_utf8_without_replacement(source, &mut self.decoded, last);
+            self.decoded.truncate(written);
+            self.lines += self.decoded.iter().filter(|&&b| b == b'\n').count() as u64;
+
+            if let DecoderResult::Malformed(length, after) = result {
+                // The sequence ends `after` bytes before the end of what was
+                // read, and may have started in an earlier buffer
+                let end = read - after as usize;
+                match self.errors {
+                    Utf8Errors::Strict => {
+                        self.malformed = Some(Malformed {
+                            offset: (self.offset + end as u64).saturating_sub(length as u64),
+                            line: self.lines + 1,
+                            encoding: self.encoding,
+                        })
+                    }
+                    Utf8Errors::Lossy => self.decoded.extend_from_slice("\u{fffd}".as_bytes()),
+                    Utf8Errors::Bytes => {
+                        // Take the start of a sequence from an earlier buffer
+                        // from the end of the bytes read before
+                        let length = length as usize;
+                        let earlier = length.saturating_sub(end).min(self.tail.len());
+                        self.decoded
+                            .extend_from_slice(&self.tail[self.tail.len() - earlier..]);
+                        self.decoded
+                            .extend_from_slice(&source[end.saturating_sub(length)..end]);
+                    }
+                }
+            }
+
+            self.tail
+                .extend_from_slice(&source[read.saturating_sub(TAIL_LENGTH)..read]);
+            let excess = self.tail.len().saturating_sub(TAIL_LENGTH);
+            self.tail.drain(..excess);
+
+            self.position = 0;
+            self.finished = last && result == DecoderResult::InputEmpty;
+            self.offset += read as u64;
+            self.input.consume(read);
+        }
+
+        Ok(&self.decoded[self.position..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        match self.decoder {
+            Some(_) => self.position += amount,
+            None => self.input.consume(amount),
+        }
+    }
+}
+
+/// A character which could not be written in the output encoding.
+///
+/// It is returned as the inner error of an [`io::Error`] of kind
+/// [`InvalidData`](io::ErrorKind::InvalidData).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unmappable {
+    pub character: char,
+    pub encoding: &'static Encoding,
+}
+
+impl Display for Unmappable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "character `{}` (U+{:04X}) cannot be encoded in {}",
+            self.character,
+            self.character as u32,
+            self.encoding.name()
+        )
+    }
+}
+
+impl error::Error for Unmappable {}
+
+/// A writer which encodes the UTF-8 written to it.
+///
+/// Writing a character which the encoding cannot represent fails with an
+/// [`Unmappable`] error, rather than writing a replacement. Bytes which are
+/// not valid UTF-8 are written unchanged when the encoding is compatible with
+/// ASCII, and fail with an [`InvalidData`](io::ErrorKind::InvalidData) error
+/// in any other encoding, such as UTF-16, where they would corrupt the text
+/// around them. Once everything is written, the encoder is
+/// [finished](Encoder::finish).
+pub struct Encoder<W: Write> {
+    output: W,
+    encoding: &'static Encoding,
+    encoder: Option<encoding_rs::Encoder>,
+    incomplete: Vec<u8>,
+}
+
+impl<W: Write> Encoder<W> {
+    /// Encode to `encoding`, starting with a byte order mark when `bom` is
+    /// set and the encoding has one. UTF-16 always starts with a byte order
+    /// mark.
+    pub fn new(mut output: W, encoding: &'static Encoding, bom: bool) -> io::Result<Self> {
+        if bom || encoding == UTF_16LE || encoding == UTF_16BE {
+            output.write_all(self::bom(encoding))?;
+        }
+
+        Ok(Encoder::continuing(output, encoding))
+    }
+
+    /// Encode to `encoding` after the text already written to `output`, so
+    /// without a byte order mark, even in UTF-16.
+    pub fn continuing(output: W, encoding: &'static Encoding) -> Self {
+        let utf_16 = encoding == UTF_16LE || encoding == UTF_16BE;
+        Encoder {
+            output,
+            encoding,
+            encoder: if encoding == UTF_8 || utf_16 {
+                None
+            } else {
+                Some(encoding.new_encoder())
+            },
+            incomplete: Vec::new(),
+        }
+    }
+
+    /// Write anything left over, returning a stateful encoding such as
+    /// ISO-2022-JP to its initial state, and return the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let incomplete = std::mem::take(&mut self.incomplete);
+        self.write_invalid(&incomplete)?;
+        self.encode("", true)?;
+        self.output.flush()?;
+
+        Ok(self.output)
+    }
+
+    /// Write `bytes` which are not valid UTF-8 unchanged, if the encoding
+    /// allows it.
+    fn write_invalid(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if bytes.is_empty() || self.encoding.is_ascii_compatible() {
+            self.output.write_all(bytes)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "bytes which are not valid UTF-8 cannot be written unchanged in {}",
+                    self.encoding.name()
+                ),
+            ))
+        }
+    }
+
+    fn encode(&mut self, text: &str, last: bool) -> io::Result<()> {
+        if self.encoding == UTF_16LE {
+            let bytes: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+            return self.output.write_all(&bytes);
+        } else if self.encoding == UTF_16BE {
+            let bytes: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+            return self.output.write_all(&bytes);
+        }
+
+        let encoder = match &mut self.encoder {
+            Some(encoder) => encoder,
+            None => return self.output.write_all(text.as_bytes()),
+        };
+
+        let length = encoder
+            .max_buffer_length_from_utf8_without_replacement(text.len())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::OutOfMemory, "output too long"))?;
+        let mut encoded = vec![0; length];
+        let (result, _, written) =
+            encoder.encode_from_utf8_without_replacement(text, &mut encoded, last);
+        self.output.write_all(&encoded[..written])?;
+
+        match result {
+            EncoderResult::Unmappable(character) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                Unmappable {
+                    character,
+                    encoding: self.encoding,
+                },
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut bytes = std::mem::take(&mut self.incomplete);
+        bytes.extend_from_slice(buf);
+
+        let mut rest = bytes.as_slice();
+        while !rest.is_empty() {
+            match std::str::from_utf8(rest) {
+                Ok(text) => {
+                    self.encode(text, false)?;
+                    rest = &[];
+                }
+                Err(error) => {
+                    let (valid, invalid) = rest.split_at(error.valid_up_to());
+                    self.encode(std::str::from_utf8(valid).unwrap_or_default(), false)?;
+                    match error.error_len() {
+                        Some(length) => {
+                            self.write_invalid(&invalid[..length])?;
+                            rest = &invalid[length..];
+                        }
+                        None => {
+                            // Keep the start of a character split across writes
+                            self.incomplete = invalid.to_vec();
+                            rest = &[];
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        encoding_rs::{ISO_2022_JP, SHIFT_JIS, WINDOWS_1252},
+    };
+
+    fn decode(input: &[u8], encoding: Option<&'static Encoding>) -> (String, &'static Encoding) {
+        let mut decoder = Decoder::new(input, encoding).unwrap();
+        let mut decoded = String::new();
+        decoder.read_to_string(&mut decoded).unwrap();
+
+        (decoded, decoder.encoding())
+    }
+
+    fn encode(text: &str, encoding: &'static Encoding) -> io::Result<Vec<u8>> {
+        let mut encoder = Encoder::new(Vec::new(), encoding, false)?;
+        encoder.write_all(text.as_bytes())?;
+
+        encoder.finish()
+    }
+
+    #[test]
+    fn parse_encoding_labels() {
+        assert_eq!(parse_encoding("latin1").unwrap(), WINDOWS_1252);
+        assert_eq!(parse_encoding("UTF-16LE").unwrap(), UTF_16LE);
+        assert_eq!(parse_encoding("sjis").unwrap(), SHIFT_JIS);
+        assert!(parse_encoding("klingon").is_err());
+    }
+
+    #[test]
+    fn decode_legacy_encoding() {
+        assert_eq!(
+            decode(b"caf\xe9", Some(WINDOWS_1252)),
+            (String::from("café"), WINDOWS_1252)
+        );
+        assert_eq!(
+            decode(b"\x93\xfa\x96\x7b", Some(SHIFT_JIS)),
+            (String::from("日本"), SHIFT_JIS)
+        );
+    }
+
+    #[test]
+    fn decode_sniffs_bom() {
+        assert_eq!(
+            decode(b"\xff\xfeh\0i\0", None),
+            (String::from("hi"), UTF_16LE)
+        );
+        assert_eq!(
+            decode(b"\xfe\xff\0h\0i", Some(WINDOWS_1252)),
+            (String::from("hi"), UTF_16BE)
+        );
+        assert_eq!(decode(b"\xef\xbb\xbfhi", None), (String::from("hi"), UTF_8));
+        assert_eq!(decode(b"h", None), (String::from("h"), UTF_8));
+    }
+
+    #[test]
+    fn decode_utf8_unchanged() {
+        let mut decoded = Vec::new();
+        Decoder::new(&b"caf\xe9"[..], None)
+            .unwrap()
+            .read_to_end(&mut decoded)
+            .unwrap();
+
+        assert_eq!(decoded, b"caf\xe9");
+    }
+
+    fn decode_with_errors(
+        input: &[u8],
+        encoding: &'static Encoding,
+        errors: Utf8Errors,
+    ) -> io::Result<Vec<u8>> {
+        let mut decoded = Vec::new();
+        Decoder::with_errors(input, Some(encoding), errors)?.read_to_end(&mut decoded)?;
+
+        Ok(decoded)
+    }
+
+    #[test]
+    fn decode_malformed_strictly() {
+        let error =
+            decode_with_errors(b"one\nt\x82\xffo\n", SHIFT_JIS, Utf8Errors::Strict).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.get_ref().unwrap().downcast_ref::<Malformed>(),
+            Some(&Malformed {
+                offset: 5,
+                line: 2,
+                encoding: SHIFT_JIS
+            })
+        );
+        assert_eq!(
+            error.to_string(),
+            "invalid Shift_JIS at byte offset 5, line 2"
+        );
+
+        let error =
+            decode_with_errors(b"\xff\xfeh\0\0\xd8", UTF_16LE, Utf8Errors::Strict).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid UTF-16LE at byte offset 4, line 1"
+        );
+    }
+
+    #[test]
+    fn decode_malformed_lossily() {
+        assert_eq!(
+            decode_with_errors(b"t\x82\xffo", SHIFT_JIS, Utf8Errors::Lossy).unwrap(),
+            "t\u{fffd}o".as_bytes()
+        );
+        assert_eq!(
+            decode_with_errors(b"h\0\0\xd8i\0", UTF_16LE, Utf8Errors::Lossy).unwrap(),
+            "h\u{fffd}i".as_bytes()
+        );
+    }
+
+    #[test]
+    fn decode_malformed_as_bytes() {
+        assert_eq!(
+            decode_with_errors(b"t\x82\xffo", SHIFT_JIS, Utf8Errors::Bytes).unwrap(),
+            b"t\x82\xffo"
+        );
+    }
+
+    #[test]
+    fn decode_malformed_across_reads_as_bytes() {
+        // The first three bytes are read up front, then two at a time, so
+        // the malformed `\x82\xff` is split across two reads
+        let input = io::BufReader::with_capacity(2, &b"abcd\x82\xffo"[..]);
+        let mut decoded = Vec::new();
+        Decoder::with_errors(input, Some(SHIFT_JIS), Utf8Errors::Bytes)
+            .unwrap()
+            .read_to_end(&mut decoded)
+            .unwrap();
+
+        assert_eq!(decoded, b"abcd\x82\xffo");
+    }
+
+    #[test]
+    fn encode_legacy_encoding() {
+        assert_eq!(encode("CAFÉ", WINDOWS_1252).unwrap(), b"CAF\xc9");
+        assert_eq!(encode("日本", SHIFT_JIS).unwrap(), b"\x93\xfa\x96\x7b");
+    }
+
+    #[test]
+    fn encode_utf_16_with_bom() {
+        assert_eq!(encode("Hi", UTF_16LE).unwrap(), b"\xff\xfeH\0i\0");
+        assert_eq!(encode("Hi", UTF_16BE).unwrap(), b"\xfe\xff\0H\0i");
+    }
+
+    #[test]
+    fn encode_continuing_without_bom() {
+        let mut encoder = Encoder::continuing(Vec::new(), UTF_16LE);
+        encoder.write_all(b"Hi").unwrap();
+
+        assert_eq!(encoder.finish().unwrap(), b"H\0i\0");
+    }
+
+    #[test]
+    fn encode_split_character() {
+        let mut encoder = Encoder::new(Vec::new(), WINDOWS_1252, false).unwrap();
+        encoder.write_all(b"\xc3").unwrap();
+        encoder.write_all(b"\x89!").unwrap();
+
+        assert_eq!(encoder.finish().unwrap(), b"\xc9!");
+    }
+
+    #[test]
+    fn encode_stateful_encoding() {
+        assert_eq!(encode("日本", ISO_2022_JP).unwrap(), b"\x1b$BF|K\\\x1b(B");
+    }
+
+    #[test]
+    fn encode_invalid_bytes() {
+        let mut encoder = Encoder::new(Vec::new(), WINDOWS_1252, false).unwrap();
+        encoder.write_all(b"caf\xe9").unwrap();
+        assert_eq!(encoder.finish().unwrap(), b"caf\xe9");
+
+        let mut encoder = Encoder::new(Vec::new(), UTF_16LE, false).unwrap();
+        let error = encoder.write_all(b"caf\xff").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            "bytes which are not valid UTF-8 cannot be written unchanged in UTF-16LE"
+        );
+    }
+
+    #[test]
+    fn encode_unmappable_character() {
+        let error = encode("STRAẞE", WINDOWS_1252).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            "character `ẞ` (U+1E9E) cannot be encoded in windows-1252"
+        );
+    }
+}
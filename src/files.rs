@@ -0,0 +1,579 @@
+//! Find the files to convert, and the paths their conversions are written to.
+
+use {
+    crate::compress::Decompressor,
+    anyhow::{Context, Error, Result},
+    encoding_rs::{Encoding, UTF_16BE, UTF_16LE},
+    filetime::FileTime,
+    ignore::{
+        gitignore::{Gitignore, GitignoreBuilder},
+        WalkBuilder,
+    },
+    std::{
+        collections::HashSet,
+        env,
+        fs::{self, File},
+        io::{self, BufReader, Read},
+        path::{Component, Path, PathBuf},
+    },
+    tempfile::NamedTempFile,
+};
+
+/// How much of the start of a file is checked for a NUL byte, as git does, to
+/// tell whether it is binary.
+const BINARY_CHECK_LENGTH: u64 = 8000;
+
+/// Options for finding the files to convert.
+#[derive(Debug, Clone, Default)]
+pub struct Search {
+    /// Find the files in directories and their subdirectories.
+    pub recursive: bool,
+    /// Patterns of files to skip, in the syntax of a `.gitignore` file.
+    pub excludes: Vec<String>,
+    /// The encoding of the files, when it is known.
+    pub encoding: Option<&'static Encoding>,
+}
+
+/// The files found by a search.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Found {
+    /// The files to convert, in the order they were found.
+    pub files: Vec<PathBuf>,
+    /// The files skipped as binary.
+    pub binary: Vec<PathBuf>,
+}
+
+impl Found {
+    fn add(&mut self, path: PathBuf, check_binary: bool, search: &Search) -> Result<()> {
+        if check_binary && is_binary(&path, search.encoding)? {
+            self.binary.push(path);
+        } else {
+            self.files.push(path);
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether the file at `path` looks binary, having a NUL byte near its start.
+///
+/// Compressed files are checked once decompressed. Files starting with a byte
+/// order mark, or in UTF-16, are never binary.
+pub fn is_binary(path: &Path, encoding: Option<&'static Encoding>) -> io::Result<bool> {
+    if encoding == Some(UTF_16LE) || encoding == Some(UTF_16BE) {
+        return Ok(false);
+    }
+
+    let mut start = Vec::new();
+    Decompressor::new(BufReader::new(File::open(path)?))?
+        .take(BINARY_CHECK_LENGTH)
+        .read_to_end(&mut start)?;
+
+    Ok(looks_binary(&start))
+}
+
+/// Whether text starting with `start` looks binary, having a NUL byte near
+/// its start but no byte order mark.
+pub fn looks_binary(start: &[u8]) -> bool {
+    let start = &start[..start.len().min(BINARY_CHECK_LENGTH as usize)];
+    Encoding::for_bom(start).is_none() && start.contains(&0)
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// Whether `path`, or one of the directories it is in, matches one of the
+/// `patterns`.
+pub(crate) fn matches(patterns: &Gitignore, path: &Path, is_dir: bool) -> bool {
+    patterns.matched(path, is_dir).is_ignore()
+        || path
+            .ancestors()
+            .skip(1)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .any(|dir| patterns.matched(dir, true).is_ignore())
+}
+
+/// Find the files to convert from a list of inputs, each a file, a directory
+/// or a glob such as `*.txt`.
+///
+/// Directories are only allowed in a recursive search. The files found in
+/// them or by a glob are skipped when they match one of the excludes, or when
+/// they look binary. Files named as inputs are always converted.
+pub fn find(inputs: &[PathBuf], search: &Search) -> Result<Found> {
+    let mut builder = GitignoreBuilder::new(env::current_dir()?);
+    for exclude in &search.excludes {
+        builder
+            .add_line(None, exclude)
+            .with_context(|| format!("invalid exclude `{}`", exclude))?;
+    }
+    let excludes = builder.build()?;
+
+    let mut found = Found::default();
+    for input in inputs {
+        let pattern = input.to_string_lossy();
+        if !input.exists() && is_glob(&pattern) {
+            let mut matched = false;
+            for path in
+                glob::glob(&pattern).with_context(|| format!("invalid glob `{}`", pattern))?
+            {
+                let path = path?;
+                matched = true;
+                if !matches(&excludes, &path, path.is_dir()) {
+                    find_in(path, false, &excludes, search, &mut found)?;
+                }
+            }
+
+            if !matched {
+                return Err(Error::msg(format!("no files match `{}`", pattern)));
+            }
+        } else {
+            find_in(input.clone(), true, &excludes, search, &mut found)?;
+        }
+    }
+
+    // Convert a file once, however many of the inputs lead to it
+    let mut seen = HashSet::new();
+    found.files.retain(|path| seen.insert(FileId::of(path)));
+
+    Ok(found)
+}
+
+fn find_in(
+    path: PathBuf,
+    named: bool,
+    excludes: &Gitignore,
+    search: &Search,
+    found: &mut Found,
+) -> Result<()> {
+    let metadata =
+        fs::metadata(&path).with_context(|| format!("could not open `{}`", path.display()))?;
+    if !metadata.is_dir() {
+        return found.add(path, !named, search);
+    } else if !search.recursive {
+        return Err(Error::msg(format!(
+            "`{}` is a directory, use -R to convert the files in it",
+            path.display()
+        )));
+    }
+
+    let excludes = excludes.clone();
+    let walk = WalkBuilder::new(&path)
+        .standard_filters(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .filter_entry(move |entry| {
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            entry.depth() == 0 || !excludes.matched(entry.path(), is_dir).is_ignore()
+        })
+        .build();
+
+    for entry in walk {
+        let entry = entry?;
+        if entry.file_type().is_some_and(|t| t.is_file()) {
+            found.add(entry.into_path(), true, search)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// What identifies a file, whichever path leads to it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FileId {
+    /// The device and inode of a file which exists, on Unix.
+    Inode(u64, u64),
+    /// The canonical path of a file, or of the directory it would be created
+    /// in followed by its name.
+    Path(PathBuf),
+}
+
+impl FileId {
+    /// The identity of the file at `path`, once any symlinks are followed.
+    pub fn of(path: &Path) -> Self {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            if let Ok(metadata) = fs::metadata(path) {
+                return FileId::Inode(metadata.dev(), metadata.ino());
+            }
+        }
+
+        let canonical =
+            fs::canonicalize(path).or_else(|error| match (path.parent(), path.file_name()) {
+                (Some(dir), Some(name)) => {
+                    let dir = if dir.as_os_str().is_empty() {
+                        Path::new(".")
+                    } else {
+                        dir
+                    };
+                    fs::canonicalize(dir).map(|dir| dir.join(name))
+                }
+                _ => Err(error),
+            });
+
+        FileId::Path(canonical.unwrap_or_else(|_| path.to_owned()))
+    }
+}
+
+/// Whether `a` and `b` are the same file, with the same device and inode on
+/// Unix, once any symlinks are followed. A path which does not exist is never
+/// the same file as another.
+pub fn is_same_file(a: &Path, b: &Path) -> bool {
+    same_file::is_same_file(a, b).unwrap_or(false)
+}
+
+/// Whether `path` is the file standard input is read from, as when it is
+/// redirected from the file by the shell.
+pub fn is_stdin(path: &Path) -> bool {
+    match (
+        same_file::Handle::stdin(),
+        same_file::Handle::from_path(path),
+    ) {
+        (Ok(stdin), Ok(file)) => stdin == file,
+        _ => false,
+    }
+}
+
+/// The path under `dir` mirroring `path`, leaving out any root, prefix or
+/// parent directory components so that it cannot escape `dir`.
+pub fn mirror(dir: &Path, path: &Path) -> PathBuf {
+    let relative: PathBuf = path
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect();
+
+    dir.join(relative)
+}
+
+/// The path of `path` with `suffix` added to the end of its file name.
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Create the file at `path` with what `write` writes, replacing any file
+/// already there unless `no_clobber` is set.
+///
+/// The file is written to a temporary file in the same directory, which is
+/// synced to disk and only renamed to `path` once `write` succeeds, so that a
+/// failure leaves no half written file behind.
+pub fn create<F>(path: &Path, no_clobber: bool, write: F) -> Result<()>
+where
+    F: FnOnce(&mut File) -> Result<()>,
+{
+    let exists = || {
+        Error::msg(format!(
+            "not overwriting `{}`, which already exists",
+            path.display()
+        ))
+    };
+    if no_clobber && fs::symlink_metadata(path).is_ok() {
+        return Err(exists());
+    }
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut temp = NamedTempFile::new_in(dir)
+        .with_context(|| format!("could not create a file in `{}`", dir.display()))?;
+    write(temp.as_file_mut())?;
+    temp.as_file()
+        .sync_all()
+        .with_context(|| format!("could not write `{}`", path.display()))?;
+
+    let persisted = if no_clobber {
+        temp.persist_noclobber(path)
+    } else {
+        temp.persist(path)
+    };
+    persisted.map_err(|error| match error.error.kind() {
+        io::ErrorKind::AlreadyExists => exists(),
+        _ => Error::new(error.error).context(format!("could not write file `{:?}`", path)),
+    })?;
+
+    Ok(())
+}
+
+/// Rewrite the file at `path`, or the file it links to, with what `write`
+/// writes, optionally keeping the original as a backup with `suffix` added to
+/// its name.
+///
+/// The file is written to a temporary file in the same directory, which is
+/// synced to disk and then renamed over the original, so that it is never
+/// left half written. Its permissions and modification time are kept where
+/// possible. A backup which would be the file itself, such as one with an
+/// empty suffix or a link to the file, is refused.
+pub fn rewrite<F>(path: &Path, backup: Option<&str>, write: F) -> Result<()>
+where
+    F: FnOnce(&mut File) -> Result<()>,
+{
+    let path =
+        fs::canonicalize(path).with_context(|| format!("could not open `{}`", path.display()))?;
+    let metadata = fs::metadata(&path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    let backup = backup.map(|suffix| with_suffix(&path, suffix));
+    if let Some(backup) = &backup {
+        if is_same_file(&path, backup) {
+            return Err(Error::msg(format!(
+                "the backup `{}` is the file being rewritten",
+                backup.display()
+            )));
+        }
+    }
+
+    let mut temp = NamedTempFile::new_in(dir)
+        .with_context(|| format!("could not create a file in `{}`", dir.display()))?;
+    write(temp.as_file_mut())?;
+
+    // Neither can be kept on every file system, which is no reason to fail
+    let _ = fs::set_permissions(temp.path(), metadata.permissions());
+    let _ = filetime::set_file_mtime(
+        temp.path(),
+        FileTime::from_last_modification_time(&metadata),
+    );
+
+    temp.as_file()
+        .sync_all()
+        .with_context(|| format!("could not write `{}`", path.display()))?;
+
+    if let Some(backup) = backup {
+        if fs::symlink_metadata(&backup).is_ok() {
+            fs::remove_file(&backup)?;
+        }
+        fs::hard_link(&path, &backup)
+            .or_else(|_| fs::copy(&path, &backup).map(|_| ()))
+            .with_context(|| format!("could not back up to `{}`", backup.display()))?;
+    }
+
+    temp.persist(&path)
+        .map_err(|error| error.error)
+        .with_context(|| format!("could not replace `{}`", path.display()))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::io::Write, tempfile::TempDir};
+
+    fn tree() -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, contents) in [
+            ("b.txt", &b"b"[..]),
+            ("a.txt", b"a"),
+            ("data.bin", b"\x00\x01"),
+            ("notes.log", b"log"),
+            ("sub/c.txt", b"c"),
+            ("target/d.txt", b"d"),
+        ] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        dir
+    }
+
+    fn names(dir: &TempDir, paths: &[PathBuf]) -> Vec<String> {
+        paths
+            .iter()
+            .map(|path| {
+                let path = path.strip_prefix(dir.path()).unwrap();
+                path.to_string_lossy().replace('\\', "/")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn find_recursive_with_excludes() {
+        let dir = tree();
+        let search = Search {
+            recursive: true,
+            excludes: vec![String::from("*.log"), String::from("target/")],
+            ..Search::default()
+        };
+
+        let found = find(&[dir.path().to_owned()], &search).unwrap();
+
+        assert_eq!(names(&dir, &found.files), ["a.txt", "b.txt", "sub/c.txt"]);
+        assert_eq!(names(&dir, &found.binary), ["data.bin"]);
+    }
+
+    #[test]
+    fn find_glob() {
+        let dir = tree();
+        let pattern = dir.path().join("*.txt");
+
+        let found = find(&[pattern], &Search::default()).unwrap();
+
+        assert_eq!(names(&dir, &found.files), ["a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn find_named_files_once() {
+        let dir = tree();
+        let inputs = [
+            dir.path().join("data.bin"),
+            dir.path().join("a.txt"),
+            dir.path().join("*.txt"),
+        ];
+
+        let found = find(&inputs, &Search::default()).unwrap();
+
+        assert_eq!(names(&dir, &found.files), ["data.bin", "a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn find_same_file_once() {
+        let dir = tree();
+        let mut inputs = vec![dir.path().join("a.txt"), dir.path().join("sub/../a.txt")];
+
+        #[cfg(unix)]
+        {
+            let link = dir.path().join("link.txt");
+            std::os::unix::fs::symlink(dir.path().join("a.txt"), &link).unwrap();
+            inputs.push(link);
+        }
+
+        let found = find(&inputs, &Search::default()).unwrap();
+
+        assert_eq!(names(&dir, &found.files), ["a.txt"]);
+    }
+
+    #[test]
+    fn find_errors() {
+        let dir = tree();
+
+        assert!(find(&[dir.path().to_owned()], &Search::default()).is_err());
+        assert!(find(&[dir.path().join("*.md")], &Search::default()).is_err());
+        assert!(find(&[dir.path().join("missing.txt")], &Search::default()).is_err());
+    }
+
+    #[test]
+    fn binary_files() {
+        let dir = tree();
+        fs::write(dir.path().join("utf16.txt"), b"\xff\xfeh\0i\0").unwrap();
+
+        assert!(is_binary(&dir.path().join("data.bin"), None).unwrap());
+        assert!(!is_binary(&dir.path().join("data.bin"), Some(UTF_16LE)).unwrap());
+        assert!(!is_binary(&dir.path().join("utf16.txt"), None).unwrap());
+        assert!(!is_binary(&dir.path().join("a.txt"), None).unwrap());
+    }
+
+    #[test]
+    fn rewrite_with_backup() {
+        let dir = tree();
+        let path = dir.path().join("a.txt");
+        let modified = FileTime::from_unix_time(1_000_000_000, 0);
+        filetime::set_file_mtime(&path, modified).unwrap();
+
+        rewrite(&path, Some(".bak"), |file| {
+            file.write_all(b"A")?;
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"A");
+        assert_eq!(fs::read(dir.path().join("a.txt.bak")).unwrap(), b"a");
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(FileTime::from_last_modification_time(&metadata), modified);
+    }
+
+    #[test]
+    fn rewrite_refuses_backup_over_itself() {
+        let dir = tree();
+        let path = dir.path().join("a.txt");
+
+        let result = rewrite(&path, Some(""), |file| {
+            file.write_all(b"A")?;
+            Ok(())
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"a");
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&path, dir.path().join("a.txt.bak")).unwrap();
+            assert!(rewrite(&path, Some(".bak"), |_| Ok(())).is_err());
+            assert_eq!(fs::read(&path).unwrap(), b"a");
+        }
+    }
+
+    #[test]
+    fn create_only_on_success() {
+        let dir = tree();
+        let path = dir.path().join("new.txt");
+
+        let result = create(&path, false, |file| {
+            file.write_all(b"half")?;
+            Err(Error::msg("failed"))
+        });
+        assert!(result.is_err());
+        assert!(!path.exists());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 6);
+
+        create(&path, true, |file| Ok(file.write_all(b"new")?)).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert!(create(&path, true, |_| Ok(())).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+    }
+
+    #[test]
+    fn rewrite_failure_keeps_original() {
+        let dir = tree();
+        let path = dir.path().join("a.txt");
+
+        let result = rewrite(&path, None, |file| {
+            file.write_all(b"half")?;
+            Err(Error::msg("failed"))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"a");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 6);
+    }
+
+    #[test]
+    fn same_files() {
+        let dir = tree();
+        let path = dir.path().join("a.txt");
+
+        assert!(is_same_file(&path, &dir.path().join("sub/../a.txt")));
+        assert!(!is_same_file(&path, &dir.path().join("b.txt")));
+        assert!(!is_same_file(&path, &dir.path().join("missing.txt")));
+
+        #[cfg(unix)]
+        {
+            let link = dir.path().join("link.txt");
+            std::os::unix::fs::symlink(&path, &link).unwrap();
+            assert!(is_same_file(&path, &link));
+        }
+    }
+
+    #[test]
+    fn mirror_paths() {
+        let out = Path::new("out");
+
+        assert_eq!(
+            mirror(out, Path::new("src/a.txt")),
+            Path::new("out/src/a.txt")
+        );
+        assert_eq!(mirror(out, Path::new("./a.txt")), Path::new("out/a.txt"));
+        assert_eq!(mirror(out, Path::new("../a.txt")), Path::new("out/a.txt"));
+        assert_eq!(
+            mirror(out, Path::new("/tmp/a.txt")),
+            Path::new("out/tmp/a.txt")
+        );
+    }
+
+    #[test]
+    fn suffix_paths() {
+        assert_eq!(
+            with_suffix(Path::new("src/a.txt"), ".angry.txt"),
+            Path::new("src/a.txt.angry.txt")
+        );
+    }
+}
@@ -0,0 +1,39 @@
+//! Take text and make it aNgrY by applying random capitalisation.
+//!
+//! The [`convert`] module holds the [`Convertor`] trait and its
+//! implementations, while the [`driver`] module runs a convertor over a
+//! stream of lines. Convertors can be chained together from a spec with the
+//! [`pipeline`] module.
+//!
+//! With the features of the same names, text in other encodings is read and
+//! written with the `encoding` module and compressed with the `compress`
+//! module, the `files` module finds the files to convert, and the `archive`
+//! module converts the members of archives. The `cli` feature, on by default,
+//! enables them all.
+//!
+//! ```
+//! use angry::{driver, Conversion};
+//!
+//! let mut convertor = Conversion::Uppercase.convertor();
+//! let mut output = Vec::new();
+//! driver::convert(convertor.as_mut(), "some text".as_bytes(), &mut output).unwrap();
+//!
+//! assert_eq!(output, b"SOME TEXT");
+//! ```
+
+#[cfg(feature = "archive")]
+pub mod archive;
+#[cfg(feature = "compress")]
+pub mod compress;
+pub mod convert;
+pub mod driver;
+#[cfg(feature = "encoding")]
+pub mod encoding;
+#[cfg(feature = "files")]
+pub mod files;
+pub mod pipeline;
+
+pub use crate::{
+    convert::{Conversion, Convertor, Settings},
+    pipeline::Pipeline,
+};
@@ -1,5 +1,4 @@
 mod cli;
-mod convert;
 
 use {crate::cli::Cli, anyhow::Result, structopt::StructOpt};
 
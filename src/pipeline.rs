@@ -0,0 +1,376 @@
+//! Parse a chain of convertors from a spec such as
+//! `random:p=70,s=10 | leet:level=1 | punct`.
+
+use {
+    crate::convert::{combinator::Chain, parse_percent, Conversion, Convertor, Settings},
+    anyhow::{Context, Error, Result},
+    std::{
+        collections::HashMap,
+        fmt::{self, Display, Formatter},
+        str::FromStr,
+    },
+};
+
+/// A parameter accepted by a pipeline [`Stage`].
+#[derive(Debug)]
+pub struct Param {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub default: Option<&'static str>,
+}
+
+/// A convertor which can be used as a stage of a [`Pipeline`].
+#[derive(Debug)]
+pub struct Stage {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub params: &'static [Param],
+}
+
+const PERCENT: Param = Param {
+    name: "p",
+    description: "Percentage chance of applying the change to each letter",
+    default: Some("50"),
+};
+
+/// Every stage that can be used in a pipeline spec.
+pub const STAGES: &[Stage] = &[
+    Stage {
+        name: "upper",
+        description: "Convert the text to uppercase",
+        params: &[],
+    },
+    Stage {
+        name: "lower",
+        description: "Convert the text to lowercase",
+        params: &[],
+    },
+    Stage {
+        name: "reverse",
+        description: "Reverse the case of the text",
+        params: &[],
+    },
+    Stage {
+        name: "title",
+        description: "Convert the text to title case",
+        params: &[],
+    },
+    Stage {
+        name: "random",
+        description: "Convert the text to random case",
+        params: &[
+            PERCENT,
+            Param {
+                name: "s",
+                description: "Step increase of percent if not flipped",
+                default: None,
+            },
+        ],
+    },
+    Stage {
+        name: "alternate",
+        description: "Strictly alternate the case of the letters",
+        params: &[
+            Param {
+                name: "start",
+                description: "Case of the first letter, lower or upper",
+                default: Some("lower"),
+            },
+            Param {
+                name: "restart",
+                description: "Where to go back to the first case, never, word, line or sentence",
+                default: Some("never"),
+            },
+        ],
+    },
+    Stage {
+        name: "leet",
+        description: "Substitute letters with digits and symbols",
+        params: &[
+            Param {
+                name: "level",
+                description: "Level of substitutions, from 1 (light) to 3 (full)",
+                default: Some("1"),
+            },
+            PERCENT,
+        ],
+    },
+    Stage {
+        name: "punct",
+        description: "Make sentence punctuation angry",
+        params: &[],
+    },
+];
+
+impl Stage {
+    /// Find the stage with the given name.
+    pub fn find(name: &str) -> Result<&'static Stage> {
+        STAGES
+            .iter()
+            .find(|stage| stage.name == name)
+            .ok_or_else(|| {
+                Error::msg(format!(
+                    "unknown stage `{}`, expected one of: {}",
+                    name,
+                    STAGES
+                        .iter()
+                        .map(|stage| stage.name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            })
+    }
+
+    fn parse(&self, params: &str) -> Result<Conversion> {
+        let mut values = HashMap::new();
+        for param in params.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (name, value) = param.split_once('=').ok_or_else(|| {
+                Error::msg(format!("parameter `{}` must be given as name=value", param))
+            })?;
+            let (name, value) = (name.trim(), value.trim());
+
+            if !self.params.iter().any(|p| p.name == name) {
+                return Err(Error::msg(format!(
+                    "unknown parameter `{}`, expected one of: {}",
+                    name,
+                    self.params
+                        .iter()
+                        .map(|p| p.name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )));
+            }
+            if values.insert(name, value).is_some() {
+                return Err(Error::msg(format!(
+                    "parameter `{}` is given more than once",
+                    name
+                )));
+            }
+        }
+
+        let param = |name: &str| {
+            values.get(name).copied().or_else(|| {
+                self.params
+                    .iter()
+                    .find(|p| p.name == name)
+                    .and_then(|p| p.default)
+            })
+        };
+        let parse = |name: &str| -> Result<&str> {
+            param(name).ok_or_else(|| Error::msg(format!("missing parameter `{}`", name)))
+        };
+
+        Ok(match self.name {
+            "upper" => Conversion::Uppercase,
+            "lower" => Conversion::Lowercase,
+            "reverse" => Conversion::Reverse,
+            "title" => Conversion::Titlecase,
+            "random" => Conversion::Random(
+                parse_percent(parse("p")?).context("invalid parameter `p`")?,
+                param("s")
+                    .map(str::parse)
+                    .transpose()
+                    .context("invalid parameter `s`")?,
+            ),
+            "alternate" => Conversion::Alternate(
+                parse("start")?
+                    .parse()
+                    .context("invalid parameter `start`")?,
+                parse("restart")?
+                    .parse()
+                    .context("invalid parameter `restart`")?,
+            ),
+            "leet" => Conversion::Leet(
+                parse("level")?
+                    .parse()
+                    .context("invalid parameter `level`")?,
+                parse_percent(parse("p")?).context("invalid parameter `p`")?,
+            ),
+            "punct" => Conversion::Punctuate,
+            name => unreachable!("stage `{}` has no conversion", name),
+        })
+    }
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<12}{}", self.name, self.description)?;
+        for param in self.params {
+            let name = match param.default {
+                Some(default) => format!("{}={}", param.name, default),
+                None => String::from(param.name),
+            };
+            write!(f, "\n    {:<16}{}", name, param.description)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A chain of conversions, each run over the output of the one before.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pipeline {
+    conversions: Vec<Conversion>,
+}
+
+impl Pipeline {
+    /// The conversions in the pipeline, in order.
+    pub fn conversions(&self) -> &[Conversion] {
+        &self.conversions
+    }
+
+    /// Build the convertor which runs the whole pipeline.
+    pub fn convertor(&self) -> Box<dyn Convertor> {
+        self.convertor_with(&Settings::default())
+    }
+
+    /// Build the convertor which runs the whole pipeline with the given
+    /// `settings`, each stage's seed moved on by its position.
+    pub fn convertor_with(&self, settings: &Settings) -> Box<dyn Convertor> {
+        self.conversions
+            .iter()
+            .zip(0..)
+            .map(|(conversion, index)| {
+                conversion.convertor_with(&Settings {
+                    randomness: settings.randomness.offset(index),
+                    ..settings.clone()
+                })
+            })
+            .reduce(Chain::new)
+            .expect("a pipeline has at least one conversion")
+    }
+}
+
+impl From<Conversion> for Pipeline {
+    fn from(conversion: Conversion) -> Self {
+        Pipeline {
+            conversions: vec![conversion],
+        }
+    }
+}
+
+impl FromStr for Pipeline {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let conversions = s
+            .split('|')
+            .map(str::trim)
+            .map(|spec| {
+                let (name, params) = spec.split_once(':').unwrap_or((spec, ""));
+                let name = name.trim();
+                if name.is_empty() {
+                    return Err(Error::msg("empty stage in pipeline"));
+                }
+
+                Stage::find(name)?
+                    .parse(params)
+                    .with_context(|| format!("invalid stage `{}`", spec))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Pipeline { conversions })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::convert::{alternate::Case, alternate::Restart, leet::Level},
+    };
+
+    #[test]
+    fn parse_pipeline() {
+        let pipeline: Pipeline = "random:p=70,s=10 | leet:level=1 | punct".parse().unwrap();
+
+        assert_eq!(
+            pipeline.conversions(),
+            &[
+                Conversion::Random(70, Some(10)),
+                Conversion::Leet(Level::Light, 50),
+                Conversion::Punctuate
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_pipeline_defaults() {
+        let pipeline: Pipeline = "random|alternate:restart=word".parse().unwrap();
+
+        assert_eq!(
+            pipeline.conversions(),
+            &[
+                Conversion::Random(50, None),
+                Conversion::Alternate(Case::Lower, Restart::Word)
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_pipeline_unknown_stage() {
+        let error = "upper | shout".parse::<Pipeline>().unwrap_err();
+
+        assert!(error.to_string().starts_with("unknown stage `shout`"));
+    }
+
+    #[test]
+    fn parse_pipeline_unknown_param() {
+        let error = "random:q=10".parse::<Pipeline>().unwrap_err();
+
+        assert_eq!(
+            format!("{:#}", error),
+            "invalid stage `random:q=10`: unknown parameter `q`, expected one of: p, s"
+        );
+    }
+
+    #[test]
+    fn parse_pipeline_bad_percent() {
+        let error = "random:p=101".parse::<Pipeline>().unwrap_err();
+
+        assert_eq!(
+            format!("{:#}", error),
+            "invalid stage `random:p=101`: invalid parameter `p`: percentage must be between 0 and 100, was 101"
+        );
+    }
+
+    #[test]
+    fn parse_pipeline_repeated_param() {
+        let error = "random:p=10,p=20".parse::<Pipeline>().unwrap_err();
+
+        assert_eq!(
+            format!("{:#}", error),
+            "invalid stage `random:p=10,p=20`: parameter `p` is given more than once"
+        );
+    }
+
+    #[test]
+    fn parse_every_stage() {
+        for stage in STAGES {
+            assert!(stage.name.parse::<Pipeline>().is_ok(), "{}", stage.name);
+        }
+    }
+
+    #[test]
+    fn parse_pipeline_empty_stage() {
+        assert!("upper||lower".parse::<Pipeline>().is_err());
+    }
+
+    #[test]
+    fn pipeline_convert() {
+        let pipeline: Pipeline = "upper | leet:p=100 | punct".parse().unwrap();
+        let mut c = pipeline.convertor();
+
+        assert_eq!(c.convert(String::from("leet speak.")), "L337 $P34K!");
+    }
+
+    #[test]
+    fn stage_display() {
+        assert_eq!(
+            Stage::find("leet").unwrap().to_string(),
+            "leet        Substitute letters with digits and symbols\n    \
+             level=1         Level of substitutions, from 1 (light) to 3 (full)\n    \
+             p=50            Percentage chance of applying the change to each letter"
+        );
+    }
+}
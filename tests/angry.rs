@@ -1,16 +1,29 @@
 use {
-    assert_cmd::Command,
+    assert_cmd::{assert::OutputAssertExt, cargo::CommandCargoExt, Command},
     predicate::str,
     predicates::prelude::*,
-    std::io::{Read, Seek, SeekFrom, Write},
+    std::{
+        fs,
+        io::{Read, Seek, SeekFrom, Write},
+    },
     tempfile::NamedTempFile,
 };
 
 type CmdResult = Result<(), Box<dyn std::error::Error>>;
 
+/// The angry command, with no locale in its environment.
+fn angry() -> Result<Command, Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("angry")?;
+    cmd.env_remove("LANG")
+        .env_remove("LC_ALL")
+        .env_remove("LC_CTYPE");
+
+    Ok(cmd)
+}
+
 #[test]
 fn unspecified_content_arg() -> CmdResult {
-    let mut cmd = Command::cargo_bin("angry")?;
+    let mut cmd = angry()?;
     cmd.arg("a little bit OF TEXT")
         .assert()
         .failure()
@@ -24,7 +37,7 @@ fn unspecified_content_arg() -> CmdResult {
 
 #[test]
 fn uppercase_content_arg() -> CmdResult {
-    let mut cmd = Command::cargo_bin("angry")?;
+    let mut cmd = angry()?;
     cmd.arg("--uppercase")
         .arg("a little bit OF TEXT")
         .assert()
@@ -39,7 +52,7 @@ fn uppercase_content_arg() -> CmdResult {
 fn uppercase_content_arg_to_file() -> CmdResult {
     let mut file = NamedTempFile::new()?;
 
-    let mut cmd = Command::cargo_bin("angry")?;
+    let mut cmd = angry()?;
     cmd.arg("--uppercase")
         .arg("a little bit OF TEXT")
         .arg("--output")
@@ -64,7 +77,7 @@ fn uppercase_content_in_file() -> CmdResult {
     let mut file = NamedTempFile::new()?;
     writeln!(file, "this is some TEXT")?;
 
-    let mut cmd = Command::cargo_bin("angry")?;
+    let mut cmd = angry()?;
     cmd.arg("--uppercase")
         .arg("--input")
         .arg(file.path())
@@ -80,7 +93,7 @@ fn uppercase_content_in_file_multiline() -> CmdResult {
     let mut file = NamedTempFile::new()?;
     writeln!(file, "this is some text\nit covers at least\nthree lines!")?;
 
-    let mut cmd = Command::cargo_bin("angry")?;
+    let mut cmd = angry()?;
     cmd.arg("--uppercase")
         .arg("--input")
         .arg(file.path())
@@ -95,7 +108,7 @@ fn uppercase_content_in_file_multiline() -> CmdResult {
 
 #[test]
 fn uppercase_content_stdin() -> CmdResult {
-    let mut cmd = Command::cargo_bin("angry")?;
+    let mut cmd = angry()?;
     cmd.arg("--uppercase")
         .write_stdin("this is some text")
         .assert()
@@ -107,7 +120,7 @@ fn uppercase_content_stdin() -> CmdResult {
 
 #[test]
 fn file_doesnt_exist() -> CmdResult {
-    let mut cmd = Command::cargo_bin("angry")?;
+    let mut cmd = angry()?;
     cmd.arg("--uppercase")
         .args(["--input", "test/file/doesnt/exist"])
         .assert()
@@ -119,7 +132,7 @@ fn file_doesnt_exist() -> CmdResult {
 
 #[test]
 fn lowercase_content_arg() -> CmdResult {
-    let mut cmd = Command::cargo_bin("angry")?;
+    let mut cmd = angry()?;
     cmd.arg("--lowercase")
         .arg("a little bit OF TEXT")
         .assert()
@@ -131,7 +144,7 @@ fn lowercase_content_arg() -> CmdResult {
 
 #[test]
 fn reverse_content_arg() -> CmdResult {
-    let mut cmd = Command::cargo_bin("angry")?;
+    let mut cmd = angry()?;
     cmd.arg("--reverse")
         .arg("a little bit OF TEXT")
         .assert()
@@ -143,7 +156,7 @@ fn reverse_content_arg() -> CmdResult {
 
 #[test]
 fn random_content_arg() -> CmdResult {
-    let mut cmd = Command::cargo_bin("angry")?;
+    let mut cmd = angry()?;
     cmd.arg("--random")
         .arg("a little bit OF TEXT")
         .assert()
@@ -152,3 +165,1079 @@ fn random_content_arg() -> CmdResult {
 
     Ok(())
 }
+
+#[test]
+fn random_content_arg_with_seed() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--random")
+        .args(["--seed", "42"])
+        .arg("simple string")
+        .assert()
+        .success()
+        .stdout(predicate::eq("simPle sTrINg\n"));
+
+    Ok(())
+}
+
+#[test]
+fn random_stable_repeats_lines() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--random")
+        .arg("--stable")
+        .write_stdin("simple string\nanother line\nsimple string\n")
+        .assert()
+        .success()
+        .stdout(predicate::function(|s: &str| {
+            let lines: Vec<&str> = s.lines().collect();
+            lines.len() == 3 && lines[0] == lines[2]
+        }));
+
+    Ok(())
+}
+
+#[test]
+fn alternate_content_arg() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--alternate")
+        .args(["--restart", "line"])
+        .write_stdin("it's a test\nit's a test\n")
+        .assert()
+        .success()
+        .stdout(predicate::eq("iT's A tEsT\niT's A tEsT\n"));
+
+    Ok(())
+}
+
+#[test]
+fn leet_content_arg() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--leet")
+        .args(["--level", "3", "--percent", "100"])
+        .arg("hack the web")
+        .assert()
+        .success()
+        .stdout(predicate::eq("|-|4(|< 7|-|3 \\/\\/38\n"));
+
+    Ok(())
+}
+
+#[test]
+fn pipeline_content_arg() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.args(["--pipeline", "upper | leet:p=100 | punct"])
+        .arg("leet speak.")
+        .assert()
+        .success()
+        .stdout(predicate::eq("L337 $P34K!\n"));
+
+    Ok(())
+}
+
+#[test]
+fn pipeline_unknown_stage() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.args(["--pipeline", "upper | shout"])
+        .arg("some text")
+        .assert()
+        .failure()
+        .stderr(str::contains("unknown stage `shout`"));
+
+    Ok(())
+}
+
+#[test]
+fn list_convertors() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--list-convertors")
+        .assert()
+        .success()
+        .stdout(str::contains("random").and(str::contains("level=1")));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_content_arg_with_combining_marks() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("cafe\u{301} \u{3b1}\u{345}")
+        .assert()
+        .success()
+        .stdout(predicate::eq("CAFE\u{301} \u{391}\u{345}\n"));
+
+    Ok(())
+}
+
+#[test]
+fn lowercase_content_arg_with_final_sigma() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--lowercase")
+        .arg("ΟΔΟΣ ΟΔΥΣΣΕΥΣ")
+        .assert()
+        .success()
+        .stdout(predicate::eq("οδος οδυσσευς\n"));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_content_arg_with_locale() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .args(["--locale", "tr"])
+        .arg("istanbul")
+        .assert()
+        .success()
+        .stdout(predicate::eq("İSTANBUL\n"));
+
+    Ok(())
+}
+
+#[test]
+fn unknown_locale() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .args(["--locale", "garbage"])
+        .arg("istanbul")
+        .assert()
+        .failure()
+        .stderr(str::contains("unknown locale `garbage`"));
+
+    Ok(())
+}
+
+#[test]
+fn seeded_v1_ignores_locale_from_env() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.args(["-a", "--seed", "7", "--algorithm", "v1"])
+        .env("LANG", "tr_TR.UTF-8")
+        .arg("istanbul iii IIII")
+        .assert()
+        .success()
+        .stdout(predicate::eq("IStanbuL Iii iiIi\n"));
+
+    Ok(())
+}
+
+#[test]
+fn lowercase_content_arg_with_locale_from_env() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--lowercase")
+        .env("LANG", "tr_TR.UTF-8")
+        .arg("DİYARBAKIR")
+        .assert()
+        .success()
+        .stdout(predicate::eq("diyarbakır\n"));
+
+    Ok(())
+}
+
+#[test]
+fn titlecase_content_arg_with_digraphs() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--titlecase")
+        .arg("ǆEMAL bijeljina ǉubljana")
+        .assert()
+        .success()
+        .stdout(predicate::eq("ǅemal Bijeljina ǈubljana\n"));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_content_arg_preserving_length() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("--preserve-length")
+        .arg("| straße | ﬁx |")
+        .assert()
+        .success()
+        .stdout(predicate::eq("| STRAẞE | ﬁX |\n"));
+
+    Ok(())
+}
+
+#[test]
+fn random_content_arg_with_normalize() -> CmdResult {
+    let convert = |text: &str| -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let output = angry()?
+            .arg("--random")
+            .args(["--seed", "42", "--normalize", "nfc"])
+            .arg(text)
+            .output()?;
+        Ok(output.stdout)
+    };
+
+    let composed = String::from_utf8(convert("cr\u{e8}me br\u{fb}l\u{e9}e")?)?;
+    let decomposed = String::from_utf8(convert("cre\u{300}me bru\u{302}le\u{301}e")?)?;
+    assert_eq!(decomposed, composed);
+    assert!(!composed.contains(|c| matches!(c, '\u{300}'..='\u{36f}')));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_content_arg_with_target() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .args(["--target", "script:latin"])
+        .arg("hello привет")
+        .assert()
+        .success()
+        .stdout(predicate::eq("HELLO привет\n"));
+
+    Ok(())
+}
+
+#[test]
+fn random_content_arg_with_bad_target() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--random")
+        .args(["--target", "script:klingon"])
+        .arg("hello")
+        .assert()
+        .failure()
+        .stderr(str::contains("unknown script `klingon`"));
+
+    Ok(())
+}
+
+#[test]
+fn random_content_arg_with_caseless_script() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--random")
+        .args(["--percent", "100"])
+        .args(["--algorithm", "v3"])
+        .arg("本当に!")
+        .assert()
+        .success()
+        .stdout(predicate::eq("本当に！\n"));
+
+    Ok(())
+}
+
+#[test]
+fn random_emphasises_caseless_script_by_default() -> CmdResult {
+    // Every `!` and digit has an even chance of emphasis, so it is next to
+    // impossible for all of them to be left alone
+    let text = "本当に!第1位".repeat(20);
+
+    let mut cmd = angry()?;
+    let output = cmd.arg("--random").arg(&text).output()?;
+
+    assert!(output.status.success());
+    assert_ne!(String::from_utf8(output.stdout)?, format!("{}\n", text));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_stdin_keeps_line_endings() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .write_stdin("crlf\r\nlone\rcr\nno newline")
+        .assert()
+        .success()
+        .stdout(predicate::eq("CRLF\r\nLONE\rCR\nNO NEWLINE"));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_stdin_with_unix_line_endings() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("--unix-line-endings")
+        .write_stdin("crlf\r\nno newline")
+        .assert()
+        .success()
+        .stdout(predicate::eq("CRLF\nNO NEWLINE\n"));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_stdin_with_null_data() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("-z")
+        .write_stdin("./one file\0./two\nlines\0")
+        .assert()
+        .success()
+        .stdout(predicate::eq("./ONE FILE\0./TWO\nLINES\0"));
+
+    Ok(())
+}
+
+#[test]
+fn titlecase_stdin_with_record_separator() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--titlecase")
+        .args(["--record-separator", "\\t"])
+        .write_stdin("one\ttwo three\t")
+        .assert()
+        .success()
+        .stdout(predicate::eq("One\tTwo Three\t"));
+
+    Ok(())
+}
+
+#[test]
+fn alternate_stdin_by_paragraph() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--alternate")
+        .args(["--restart", "line"])
+        .arg("--paragraph")
+        .write_stdin("abc\nde\n\n\nabc\n")
+        .assert()
+        .success()
+        .stdout(predicate::eq("aBc\nDe\n\n\naBc\n"));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_stdin_with_invalid_utf8() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .write_stdin(&b"one\ntw\xffo\n"[..])
+        .assert()
+        .failure()
+        .stdout(predicate::eq("ONE\n"))
+        .stderr(str::contains("invalid UTF-8 at byte offset 6, line 2"));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_stdin_with_invalid_utf8_lossy() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("--lossy")
+        .write_stdin(&b"tw\xffo\n"[..])
+        .assert()
+        .success()
+        .stdout(predicate::eq("TW\u{fffd}O\n"));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_stdin_with_invalid_utf8_bytes() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("--bytes")
+        .write_stdin(&b"caf\xe9 au lait\n"[..])
+        .assert()
+        .success()
+        .stdout(predicate::eq(&b"CAF\xe9 AU LAIT\n"[..]));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_stdin_with_input_encoding() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .args(["--input-encoding", "windows-1252"])
+        .write_stdin(&b"caf\xe9\n"[..])
+        .assert()
+        .success()
+        .stdout(predicate::eq(&b"CAF\xc9\n"[..]));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_stdin_with_utf_16_bom() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .args(["--output-encoding", "utf-8"])
+        .write_stdin(&b"\xff\xfeh\0i\0\n\0"[..])
+        .assert()
+        .success()
+        .stdout(predicate::eq(&b"\xef\xbb\xbfHI\n"[..]));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_stdin_with_malformed_input_encoding() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .args(["--input-encoding", "shift_jis"])
+        .write_stdin(&b"one\nt\x82\xffo\n"[..])
+        .assert()
+        .failure()
+        .stdout(predicate::eq("ONE\n"))
+        .stderr(str::contains("invalid Shift_JIS at byte offset 5, line 2"));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_stdin_with_unpaired_surrogate() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .write_stdin(&b"\xff\xfeh\0\n\0\0\xd8i\0\n\0"[..])
+        .assert()
+        .failure()
+        .stderr(str::contains("invalid UTF-16LE at byte offset 6, line 2"));
+
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("--lossy")
+        .args(["--output-encoding", "utf-8"])
+        .write_stdin(&b"\xff\xfeh\0\n\0\0\xd8i\0\n\0"[..])
+        .assert()
+        .success()
+        .stdout(predicate::eq("\u{feff}H\n\u{fffd}I\n"));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_bytes_to_utf_16() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("--bytes")
+        .args(["--output-encoding", "utf-16le"])
+        .write_stdin(&b"caf\xe9\n"[..])
+        .assert()
+        .failure()
+        .stderr(str::contains(
+            "--bytes cannot write invalid input unchanged in UTF-16LE",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_content_arg_with_unmappable_output() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("--preserve-length")
+        .args(["--output-encoding", "latin1"])
+        .arg("straße")
+        .assert()
+        .failure()
+        .stderr(str::contains(
+            "character `ẞ` (U+1E9E) cannot be encoded in windows-1252",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_multiple_input_files() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("a.txt"), "first\n")?;
+    fs::write(dir.path().join("b.txt"), "second\n")?;
+
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("-i")
+        .arg(dir.path().join("b.txt"))
+        .arg("-i")
+        .arg(dir.path().join("a.*"))
+        .assert()
+        .success()
+        .stdout(predicate::eq("SECOND\nFIRST\n"));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_input_files_with_bom() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("a.txt"), b"\xff\xfea\0\n\0")?;
+    fs::write(dir.path().join("b.txt"), b"\xff\xfeb\0\n\0")?;
+
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("-i")
+        .arg(dir.path().join("a.txt"))
+        .arg("-i")
+        .arg(dir.path().join("b.txt"))
+        .assert()
+        .success()
+        .stdout(predicate::eq(&b"\xff\xfeA\0\n\0B\0\n\0"[..]));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_recursive_to_out_dir() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+    let input = dir.path().join("in");
+    let out = dir.path().join("out");
+    fs::create_dir_all(input.join("sub"))?;
+    fs::write(input.join("a.txt"), "first\n")?;
+    fs::write(input.join("sub/b.txt"), "second\n")?;
+    fs::write(input.join("skip.log"), "log\n")?;
+    fs::write(input.join("data.bin"), b"\x00\x01")?;
+
+    let mut cmd = angry()?;
+    cmd.current_dir(dir.path())
+        .arg("--uppercase")
+        .args(["-R", "-i", "in", "--exclude", "*.log", "--out-dir", "out"])
+        .assert()
+        .success()
+        .stderr(str::contains("skipping binary file"));
+
+    assert_eq!(fs::read_to_string(out.join("in/a.txt"))?, "FIRST\n");
+    assert_eq!(fs::read_to_string(out.join("in/sub/b.txt"))?, "SECOND\n");
+    assert!(!out.join("in/skip.log").exists());
+    assert!(!out.join("in/data.bin").exists());
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_suffix_over_another_input() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("a"), "first\n")?;
+    fs::write(dir.path().join("a.txt"), "second\n")?;
+
+    let mut cmd = angry()?;
+    cmd.current_dir(dir.path())
+        .arg("--uppercase")
+        .args(["--suffix", ".txt", "-i", "a", "-i", "a.txt"])
+        .assert()
+        .failure()
+        .stderr(str::contains(
+            "the conversion of `a` would be written over the input `a.txt`",
+        ));
+
+    assert_eq!(fs::read_to_string(dir.path().join("a.txt"))?, "second\n");
+    assert!(!dir.path().join("a.txt.txt").exists());
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_out_dir_with_colliding_destinations() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+    let work = dir.path().join("work");
+    fs::create_dir_all(&work)?;
+    fs::write(dir.path().join("a.txt"), "first\n")?;
+    fs::write(work.join("a.txt"), "second\n")?;
+
+    let mut cmd = angry()?;
+    cmd.current_dir(&work)
+        .arg("--uppercase")
+        .args(["-i", "../a.txt", "-i", "a.txt", "--out-dir", "out"])
+        .assert()
+        .failure()
+        .stderr(str::contains(
+            "`../a.txt` and `a.txt` would both be written to `out/a.txt`",
+        ));
+
+    assert!(!work.join("out").exists());
+
+    Ok(())
+}
+
+#[test]
+fn random_files_with_suffix_and_seed() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        fs::write(dir.path().join(name), "simple string\n")?;
+    }
+
+    let mut cmd = angry()?;
+    cmd.arg("--random")
+        .args(["--seed", "42", "--suffix", ".angry", "-R", "-i"])
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout("");
+
+    for name in ["a.txt.angry", "b.txt.angry", "c.txt.angry"] {
+        assert_eq!(
+            fs::read_to_string(dir.path().join(name))?,
+            "simPle sTrINg\n"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_directory_without_recursive() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("-i")
+        .arg(dir.path())
+        .assert()
+        .failure()
+        .stderr(str::contains("is a directory, use -R"));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_in_place() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("notes.txt");
+    fs::write(&path, "some notes\n")?;
+
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("--in-place")
+        .arg("-i")
+        .arg(&path)
+        .assert()
+        .success()
+        .stdout("");
+
+    assert_eq!(fs::read_to_string(&path)?, "SOME NOTES\n");
+    assert_eq!(fs::read_dir(dir.path())?.count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_in_place_with_backup() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("notes.txt");
+    fs::write(&path, "some notes\n")?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640))?;
+    }
+
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("--in-place=.bak")
+        .arg("-i")
+        .arg(&path)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_to_string(&path)?, "SOME NOTES\n");
+    assert_eq!(
+        fs::read_to_string(dir.path().join("notes.txt.bak"))?,
+        "some notes\n"
+    );
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o640);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn reverse_duplicate_and_symlinked_inputs() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("notes.txt");
+    fs::write(&path, "some notes\n")?;
+
+    let mut cmd = angry()?;
+    cmd.arg("--reverse")
+        .arg("-i")
+        .arg(&path)
+        .arg("-i")
+        .arg(dir.path().join(".").join("notes.txt"));
+
+    #[cfg(unix)]
+    {
+        let link = dir.path().join("link.txt");
+        std::os::unix::fs::symlink(&path, &link)?;
+        cmd.arg("-i").arg(&link);
+    }
+
+    cmd.assert().success().stdout("SOME NOTES\n");
+
+    // Rewriting the file twice would reverse it back
+    cmd.arg("--in-place").assert().success().stdout("");
+    assert_eq!(fs::read_to_string(&path)?, "SOME NOTES\n");
+
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn uppercase_in_place_with_backup_linked_to_input() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("notes.txt");
+    fs::write(&path, "some notes\n")?;
+    std::os::unix::fs::symlink(&path, dir.path().join("notes.txt.bak"))?;
+
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("--in-place=.bak")
+        .arg("-i")
+        .arg(&path)
+        .assert()
+        .failure()
+        .stderr(str::contains("is the file being rewritten"));
+
+    assert_eq!(fs::read_to_string(&path)?, "some notes\n");
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_in_place_without_input() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("--in-place")
+        .write_stdin("some text")
+        .assert()
+        .failure()
+        .stderr(str::contains("--in-place needs input files"));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_output_to_input_file() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("notes.txt");
+    fs::write(&path, "some notes\n")?;
+
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("-i")
+        .arg(&path)
+        .arg("-o")
+        .arg(&path)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_to_string(&path)?, "SOME NOTES\n");
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_output_to_one_of_many_inputs() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("notes.txt");
+    fs::write(&path, "some notes\n")?;
+    fs::write(dir.path().join("more.txt"), "more notes\n")?;
+
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("-i")
+        .arg(dir.path().join("*.txt"))
+        .arg("-o")
+        .arg(&path)
+        .assert()
+        .failure()
+        .stderr(str::contains("is also an input"));
+
+    assert_eq!(fs::read_to_string(&path)?, "some notes\n");
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_stdin_redirected_from_output() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("notes.txt");
+    fs::write(&path, "some notes\n")?;
+
+    // A real redirect, which pipe_stdin would only imitate
+    let mut cmd = std::process::Command::cargo_bin("angry")?;
+    cmd.env_remove("LANG")
+        .env_remove("LC_ALL")
+        .env_remove("LC_CTYPE");
+    cmd.arg("--uppercase")
+        .arg("-o")
+        .arg(&path)
+        .stdin(fs::File::open(&path)?)
+        .assert()
+        .failure()
+        .stderr(str::contains("is also the input"));
+
+    assert_eq!(fs::read_to_string(&path)?, "some notes\n");
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_no_clobber() -> CmdResult {
+    let file = NamedTempFile::new()?;
+
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("--no-clobber")
+        .arg("-o")
+        .arg(file.path())
+        .arg("some text")
+        .assert()
+        .failure()
+        .stderr(str::contains("which already exists"));
+
+    Ok(())
+}
+
+fn gzip(text: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(text.as_bytes())?;
+    Ok(encoder.finish()?)
+}
+
+#[test]
+fn uppercase_gzip_stdin() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .write_stdin(gzip("some text\n")?)
+        .assert()
+        .success()
+        .stdout(predicate::eq("SOME TEXT\n"));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_to_compressed_output() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("out.txt.zst");
+
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("-o")
+        .arg(&path)
+        .arg("some text")
+        .assert()
+        .success();
+
+    assert_eq!(zstd::decode_all(fs::File::open(&path)?)?, b"SOME TEXT\n");
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_gzip_in_place() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("notes.gz");
+    fs::write(&path, gzip("some notes\n")?)?;
+
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("--in-place")
+        .arg("-i")
+        .arg(&path)
+        .assert()
+        .success();
+
+    let mut text = String::new();
+    flate2::read::MultiGzDecoder::new(fs::File::open(&path)?).read_to_string(&mut text)?;
+    assert_eq!(text, "SOME NOTES\n");
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_tar_archive() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+    let input = dir.path().join("bundle.tar");
+    let output = dir.path().join("bundle-angry.tar");
+
+    let mut builder = tar::Builder::new(fs::File::create(&input)?);
+    for (name, data) in &[
+        ("notes.txt", &b"some notes\n"[..]),
+        ("image.png", &b"\x89PNG\x00data"[..]),
+        ("build.log", &b"a log\n"[..]),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o600);
+        header.set_mtime(1_000_000_000);
+        header.set_cksum();
+        builder.append_data(&mut header, name, *data)?;
+    }
+    builder.into_inner()?;
+
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("--archive")
+        .arg(&input)
+        .arg("--exclude")
+        .arg("*.log")
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+
+    let mut archive = tar::Archive::new(fs::File::open(&output)?);
+    let mut members = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        assert_eq!(entry.header().mode()?, 0o600);
+        assert_eq!(entry.header().mtime()?, 1_000_000_000);
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        members.push((entry.path()?.to_string_lossy().into_owned(), data));
+    }
+    assert_eq!(
+        members,
+        [
+            (String::from("notes.txt"), b"SOME NOTES\n".to_vec()),
+            (String::from("image.png"), b"\x89PNG\x00data".to_vec()),
+            (String::from("build.log"), b"a log\n".to_vec()),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_zip_archive_members() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+    let input = dir.path().join("bundle.zip");
+    let output = dir.path().join("bundle-angry.zip");
+
+    let mut writer = zip::ZipWriter::new(fs::File::create(&input)?);
+    for (name, data) in &[("a.txt", "first\n"), ("b.md", "second\n")] {
+        writer.start_file(*name, zip::write::SimpleFileOptions::default())?;
+        writer.write_all(data.as_bytes())?;
+    }
+    writer.finish()?;
+
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("--archive")
+        .arg(&input)
+        .arg("--member")
+        .arg("*.txt")
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+
+    let mut archive = zip::ZipArchive::new(fs::File::open(&output)?)?;
+    let mut read = |name: &str| -> Result<String, Box<dyn std::error::Error>> {
+        let mut text = String::new();
+        archive.by_name(name)?.read_to_string(&mut text)?;
+        Ok(text)
+    };
+    assert_eq!(read("a.txt")?, "FIRST\n");
+    assert_eq!(read("b.md")?, "second\n");
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_zip_archive_with_invalid_member() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+    let input = dir.path().join("bundle.zip");
+    let output = dir.path().join("bundle-angry.zip");
+
+    let mut writer = zip::ZipWriter::new(fs::File::create(&input)?);
+    for (name, data) in &[("a.txt", &b"first\n"[..]), ("b.txt", &b"caf\xe9\n"[..])] {
+        writer.start_file(*name, zip::write::SimpleFileOptions::default())?;
+        writer.write_all(data)?;
+    }
+    writer.finish()?;
+
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("--archive")
+        .arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success()
+        .stderr(str::contains(
+            "copying member `b.txt` unchanged: invalid UTF-8 at byte offset 3, line 1",
+        ));
+
+    let mut archive = zip::ZipArchive::new(fs::File::open(&output)?)?;
+    let mut read = |name: &str| -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut data = Vec::new();
+        archive.by_name(name)?.read_to_end(&mut data)?;
+        Ok(data)
+    };
+    assert_eq!(read("a.txt")?, b"FIRST\n");
+    assert_eq!(read("b.txt")?, b"caf\xe9\n");
+
+    Ok(())
+}
+
+#[test]
+fn tar_archive_failure_leaves_no_output() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+    let input = dir.path().join("bundle.tar");
+    let output = dir.path().join("bundle-angry.tar");
+
+    let mut builder = tar::Builder::new(fs::File::create(&input)?);
+    let data = "straße\n".as_bytes();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, "notes.txt", data)?;
+    builder.into_inner()?;
+
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("--preserve-length")
+        .args(["--output-encoding", "latin1"])
+        .arg("--archive")
+        .arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .failure()
+        .stderr(str::contains("could not convert member `notes.txt`"));
+
+    assert!(!output.exists());
+    assert_eq!(fs::read_dir(dir.path())?.count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn zip_archive_to_stdout() -> CmdResult {
+    let dir = tempfile::tempdir()?;
+    let input = dir.path().join("bundle.zip");
+    zip::ZipWriter::new(fs::File::create(&input)?).finish()?;
+
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("--archive")
+        .arg(&input)
+        .assert()
+        .failure()
+        .stderr(str::contains("can only be written to a file"));
+
+    Ok(())
+}
+
+#[test]
+fn uppercase_lines_in_range() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("--lines")
+        .arg("/BEGIN/,/END/")
+        .write_stdin("before\nBEGIN here\ninside\nEND there\nafter\n")
+        .assert()
+        .success()
+        .stdout("before\nBEGIN HERE\nINSIDE\nEND THERE\nafter\n");
+
+    Ok(())
+}
+
+#[test]
+fn invalid_lines_address() -> CmdResult {
+    let mut cmd = angry()?;
+    cmd.arg("--uppercase")
+        .arg("--lines")
+        .arg("0-2")
+        .arg("some text")
+        .assert()
+        .failure()
+        .stderr(str::contains("line numbers start at 1"));
+
+    Ok(())
+}